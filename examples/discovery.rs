@@ -13,7 +13,7 @@ fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
-    // 表头：区分 Hex (用于核对) 和 Dec (用于 JSON 配置)
+    // 表头：Hex 与 Dec 两种写法都可以直接用于 JSON 配置
     println!(
         "{:<10} | {:<10} | {:<10} | {:<10} | {:<20} | {:<25} | Path",
         "VID(Hex)", "VID(Dec)", "PID(Hex)", "PID(Dec)", "Serial", "Port Path"
@@ -24,9 +24,9 @@ fn main() -> anyhow::Result<()> {
         println!(
             "0x{:<04x}     | {:<10} | 0x{:<04x}     | {:<10} | {:<20} | {:<25} | {}",
             dev.vid, // Hex 显示
-            dev.vid, // Dec 显示 (复制这个到 JSON)
+            dev.vid, // Dec 显示
             dev.pid, // Hex 显示
-            dev.pid, // Dec 显示 (复制这个到 JSON)
+            dev.pid, // Dec 显示
             dev.serial.as_deref().unwrap_or("N/A"),
             dev.port_path,
             dev.system_path
@@ -34,9 +34,10 @@ fn main() -> anyhow::Result<()> {
     }
 
     println!("[配置指南]");
-    println!("JSON 配置文件不支持十六进制。");
-    println!("请复制表格中 'VID(Dec)' 和 'PID(Dec)' 列的【十进制数字】到 device_config.json 中。");
-    println!("例如: 如果 VID(Hex) 是 0x3290，请在 JSON 中填 12944。");
+    println!(
+        "device_config.json 中的 vid/pid 可以直接填写十六进制字符串 (如 \"0x1a86\"，或 lsusb 输出的 \"1a86\")，也可以填写十进制数字。"
+    );
+    println!("例如: 如果 VID(Hex) 是 0x3290，可以在 JSON 中填 \"0x3290\" 或 12944。");
 
     Ok(())
 }
@@ -0,0 +1,448 @@
+// Global one-to-one assignment of devices to roles
+// 设备与角色的全局一对一分配
+//
+// Instead of "the first rule that matches wins", every (rule, device) pair that matches is
+// collected as a candidate, ranked by match strength, and then accepted greedily.
+// A role gets at most one device, a device gets at most one role.
+// 不再是 "第一个匹配的规则获胜": 先收集所有匹配成功的 (规则, 设备) 组合作为候选,
+// 按匹配强度排序后贪心地接受。每个角色最多一个设备, 每个设备最多一个角色。
+//
+// Tie-breaking (deterministic): strength desc -> rule order in config -> system_path asc
+// 平局规则 (确定性): 匹配强度降序 -> 配置中的规则顺序 -> system_path 升序
+//
+// When a rule is satisfied equally well by several free devices, the tie-break above is the
+// only thing deciding, and it depends on enumeration order. That is reported as an ambiguity,
+// and rules with `on_ambiguous: refuse` are left unbound instead.
+// 当多个空闲设备以相同强度满足同一条规则时, 只能靠上面的平局规则决定, 这依赖于枚举顺序。
+// 这种情况会作为歧义上报; 配置了 `on_ambiguous: refuse` 的规则则不会绑定。
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::{
+    AmbiguityPolicy, DeviceRule, MatchMethod, RawDeviceInfo, ResolvedDevice, RoleId, RuleMatch,
+};
+
+/// 一次分配的结果
+#[derive(Debug, Clone, Default)]
+pub struct Assignment {
+    /// 成功绑定的设备 (按规则顺序)
+    pub bound: Vec<ResolvedDevice>,
+    /// 匹配成功但在竞争中落选的候选
+    pub rejected: Vec<Rejection>,
+    /// 存在歧义的角色
+    pub ambiguous: Vec<Ambiguity>,
+}
+
+impl Assignment {
+    /// 查询某个角色绑定的设备
+    pub fn get(&self, role: &str) -> Option<&ResolvedDevice> {
+        self.bound.iter().find(|b| b.role == role)
+    }
+
+    /// 某个角色是否存在歧义
+    pub fn is_ambiguous(&self, role: &str) -> bool {
+        self.ambiguous.iter().any(|a| a.role == role)
+    }
+
+    /// 反向查询: 某个设备 (system_path) 被分配给了哪个角色
+    pub fn role_of(&self, system_path: &str) -> Option<&ResolvedDevice> {
+        self.bound
+            .iter()
+            .find(|b| b.device.system_path == system_path)
+    }
+}
+
+/// 歧义: 多个空闲设备以相同的匹配强度满足同一条规则
+#[derive(Debug, Clone)]
+pub struct Ambiguity {
+    pub role: RoleId,
+    /// 所有同等程度的候选设备 (按 system_path 排序, 第一个是平局规则选中的那个)
+    pub candidates: Vec<RawDeviceInfo>,
+    /// 是否因为 `AmbiguityPolicy::Refuse` 而拒绝了绑定
+    pub refused: bool,
+}
+
+/// 落选的候选
+#[derive(Debug, Clone)]
+pub struct Rejection {
+    pub role: RoleId,
+    /// 落选设备的 system_path
+    pub device: String,
+    pub match_method: MatchMethod,
+    pub reason: RejectReason,
+}
+
+/// 落选原因
+#[derive(Debug, Clone)]
+pub enum RejectReason {
+    /// 该角色已经被另一个设备占用
+    RoleTaken {
+        device: String,
+        match_method: MatchMethod,
+    },
+    /// 该设备已经被分配给了另一个角色
+    DeviceTaken {
+        role: RoleId,
+        match_method: MatchMethod,
+    },
+    /// 该角色存在歧义, 且规则要求拒绝绑定
+    Ambiguous,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            RejectReason::RoleTaken {
+                device,
+                match_method,
+            } => write!(
+                f,
+                "role '{}' was not bound to {} ({:?}): already bound to {} ({:?})",
+                self.role, self.device, self.match_method, device, match_method
+            ),
+            RejectReason::DeviceTaken { role, match_method } => write!(
+                f,
+                "role '{}' was not bound to {} ({:?}): device already bound to role '{}' ({:?})",
+                self.role, self.device, self.match_method, role, match_method
+            ),
+            RejectReason::Ambiguous => write!(
+                f,
+                "role '{}' was not bound to {} ({:?}): several devices match equally well",
+                self.role, self.device, self.match_method
+            ),
+        }
+    }
+}
+
+// A matching (rule, device) pair
+// 一个匹配成功的 (规则, 设备) 组合
+struct Candidate<'a> {
+    rule_index: usize,
+    rule: &'a DeviceRule,
+    device: &'a RawDeviceInfo,
+    match_method: MatchMethod,
+    pattern: Option<String>,
+}
+
+/// 对当前所有在线设备和所有规则做全局分配
+pub fn assign(rules: &[DeviceRule], devices: &[RawDeviceInfo]) -> Assignment {
+    // Collect every matching pair
+    // 收集所有匹配成功的组合
+    let mut candidates = vec![];
+    for (rule_index, rule) in rules.iter().enumerate() {
+        for device in devices {
+            if let Some(RuleMatch { method, pattern }) = rule.matches(device) {
+                candidates.push(Candidate {
+                    rule_index,
+                    rule,
+                    device,
+                    match_method: method,
+                    pattern,
+                });
+            }
+        }
+    }
+
+    // Strongest first, then config order, then system_path
+    // 强度高的优先, 其次是配置顺序, 最后是 system_path
+    candidates.sort_by(|a, b| {
+        b.match_method
+            .strength()
+            .cmp(&a.match_method.strength())
+            .then(a.rule_index.cmp(&b.rule_index))
+            .then(a.device.system_path.cmp(&b.device.system_path))
+    });
+
+    // Greedy acceptance
+    // 贪心接受
+    let mut by_role: HashMap<&str, (usize, &Candidate)> = HashMap::new();
+    let mut by_device: HashMap<&str, &Candidate> = HashMap::new();
+    let mut refused: HashSet<&str> = HashSet::new();
+    let mut rejected = vec![];
+    let mut ambiguous = vec![];
+
+    for (i, candidate) in candidates.iter().enumerate() {
+        let role = candidate.rule.role.as_str();
+        let path = candidate.device.system_path.as_str();
+
+        let reason = if refused.contains(role) {
+            Some(RejectReason::Ambiguous)
+        } else if let Some((_, winner)) = by_role.get(role) {
+            Some(RejectReason::RoleTaken {
+                device: winner.device.system_path.clone(),
+                match_method: winner.match_method,
+            })
+        } else {
+            by_device.get(path).map(|winner| RejectReason::DeviceTaken {
+                role: winner.rule.role.clone(),
+                match_method: winner.match_method,
+            })
+        };
+
+        if let Some(reason) = reason {
+            rejected.push(Rejection {
+                role: role.to_string(),
+                device: path.to_string(),
+                match_method: candidate.match_method,
+                reason,
+            });
+            continue;
+        }
+
+        // Other free devices this very rule matches just as well are the ambiguity.
+        // Candidates are sorted, so they directly follow the current one.
+        // 同一条规则以相同强度匹配到的其它空闲设备即为歧义。
+        // 候选列表已排序, 它们紧跟在当前候选之后。
+        let rivals: Vec<&Candidate> = candidates[i + 1..]
+            .iter()
+            .take_while(|c| {
+                c.rule_index == candidate.rule_index && c.match_method == candidate.match_method
+            })
+            .filter(|c| !by_device.contains_key(c.device.system_path.as_str()))
+            .collect();
+
+        if !rivals.is_empty() {
+            let refuse = candidate.rule.on_ambiguous == AmbiguityPolicy::Refuse;
+            ambiguous.push(Ambiguity {
+                role: role.to_string(),
+                candidates: std::iter::once(candidate)
+                    .chain(rivals)
+                    .map(|c| c.device.clone())
+                    .collect(),
+                refused: refuse,
+            });
+
+            if refuse {
+                refused.insert(role);
+                rejected.push(Rejection {
+                    role: role.to_string(),
+                    device: path.to_string(),
+                    match_method: candidate.match_method,
+                    reason: RejectReason::Ambiguous,
+                });
+                continue;
+            }
+        }
+
+        by_role.insert(role, (candidate.rule_index, candidate));
+        by_device.insert(path, candidate);
+    }
+
+    let mut winners: Vec<(usize, &Candidate)> = by_role.into_values().collect();
+    winners.sort_by_key(|(rule_index, _)| *rule_index);
+
+    Assignment {
+        bound: winners
+            .into_iter()
+            .map(|(_, c)| {
+                let node = c
+                    .rule
+                    .node
+                    .as_ref()
+                    .and_then(|n| n.select(&c.device.children));
+                // The stable link of the node `path()` will return
+                // `path()` 所返回节点的稳定链接
+                let stable_path = node
+                    .or_else(|| c.device.alt_node())
+                    .and_then(|n| n.stable_path(c.rule.prefer_link))
+                    .map(str::to_string);
+                ResolvedDevice {
+                    role: c.rule.role.clone(),
+                    device: c.device.clone(),
+                    match_method: c.match_method,
+                    matched_pattern: c.pattern.clone(),
+                    node: node.cloned(),
+                    stable_path,
+                }
+            })
+            .collect(),
+        rejected,
+        ambiguous,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{device, rule, rule_at};
+
+    fn bound(assignment: &Assignment) -> Vec<(&str, &str)> {
+        assignment
+            .bound
+            .iter()
+            .map(|b| (b.role.as_str(), b.device.port_path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn device_claimed_by_two_rules_goes_to_the_stronger_match() {
+        // The loose rule comes first in the config, the serial rule still wins
+        // 宽松规则在配置中排在前面, 但序列号规则仍然胜出
+        let rules = [rule_at("any", None, None), rule("arm", "A")];
+        let assignment = assign(&rules, &[device("A", "1-1")]);
+
+        assert_eq!(bound(&assignment), [("arm", "1-1")]);
+        assert_eq!(
+            assignment.get("arm").unwrap().match_method,
+            MatchMethod::SerialExact
+        );
+        assert!(assignment.get("any").is_none());
+        assert_eq!(assignment.role_of("/sys/devices/1-1").unwrap().role, "arm");
+
+        let [rejection] = assignment.rejected.as_slice() else {
+            panic!("unexpected rejections {:?}", assignment.rejected);
+        };
+        assert_eq!(rejection.role, "any");
+        assert_eq!(rejection.match_method, MatchMethod::VidPidOnly);
+        assert!(matches!(
+            &rejection.reason,
+            RejectReason::DeviceTaken { role, match_method: MatchMethod::SerialExact } if role == "arm"
+        ));
+        assert_eq!(
+            rejection.to_string(),
+            "role 'any' was not bound to /sys/devices/1-1 (VidPidOnly): \
+             device already bound to role 'arm' (SerialExact)"
+        );
+    }
+
+    #[test]
+    fn role_keeps_its_strongest_device() {
+        // The arm's serial is on 1-1, an unknown device sits on its configured port
+        // arm 的序列号在 1-1 上, 它配置的端口上是一个未知设备
+        let rules = [rule_at("arm", Some("A"), Some("1-2"))];
+        let assignment = assign(&rules, &[device("X", "1-2"), device("A", "1-1")]);
+
+        assert_eq!(bound(&assignment), [("arm", "1-1")]);
+        let [rejection] = assignment.rejected.as_slice() else {
+            panic!("unexpected rejections {:?}", assignment.rejected);
+        };
+        assert_eq!(rejection.device, "/sys/devices/1-2");
+        assert_eq!(rejection.match_method, MatchMethod::TopologyFallback);
+        assert!(matches!(
+            &rejection.reason,
+            RejectReason::RoleTaken { device, match_method: MatchMethod::SerialExact }
+                if device == "/sys/devices/1-1"
+        ));
+        assert_eq!(
+            rejection.to_string(),
+            "role 'arm' was not bound to /sys/devices/1-2 (TopologyFallback): \
+             already bound to /sys/devices/1-1 (SerialExact)"
+        );
+    }
+
+    #[test]
+    fn ties_break_by_rule_order_then_system_path() {
+        let devices = [device("B", "1-2"), device("A", "1-1")];
+        let rules = [
+            rule_at("first", None, Some("1-*")),
+            rule_at("second", None, Some("1-*")),
+        ];
+        assert_eq!(
+            bound(&assign(&rules, &devices)),
+            [("first", "1-1"), ("second", "1-2")]
+        );
+
+        // Neither the device order nor anything but the rule order changes the outcome
+        // 设备顺序不影响结果, 只有规则顺序会影响
+        let reversed = [devices[1].clone(), devices[0].clone()];
+        assert_eq!(
+            bound(&assign(&rules, &reversed)),
+            [("first", "1-1"), ("second", "1-2")]
+        );
+        let swapped = [rules[1].clone(), rules[0].clone()];
+        assert_eq!(
+            bound(&assign(&swapped, &devices)),
+            [("second", "1-1"), ("first", "1-2")]
+        );
+    }
+
+    #[test]
+    fn topology_fallback_ranks_below_port_matches() {
+        assert!(MatchMethod::SerialExact.strength() > MatchMethod::SerialPattern.strength());
+        assert!(MatchMethod::SerialPattern.strength() > MatchMethod::PortPath.strength());
+        assert!(MatchMethod::PortPath.strength() > MatchMethod::PortPathPattern.strength());
+        assert!(MatchMethod::PortPathPattern.strength() > MatchMethod::TopologyFallback.strength());
+        assert!(MatchMethod::TopologyFallback.strength() > MatchMethod::VidPidOnly.strength());
+
+        // A device with a foreign serial on the arm's port goes to the rule it fully satisfies
+        // arm 端口上序列号不符的设备, 分配给被它完全满足的规则
+        let rules = [
+            rule_at("arm", Some("A"), Some("1-1")),
+            rule_at("bench", None, Some("1-*")),
+        ];
+        let assignment = assign(&rules, &[device("X", "1-1")]);
+        assert_eq!(bound(&assignment), [("bench", "1-1")]);
+        assert_eq!(
+            assignment.get("bench").unwrap().match_method,
+            MatchMethod::PortPathPattern
+        );
+        assert!(matches!(
+            &assignment.rejected[0].reason,
+            RejectReason::DeviceTaken { role, .. } if role == "bench"
+        ));
+    }
+
+    #[test]
+    fn equally_strong_candidates_are_an_ambiguity() {
+        let devices = [device("B", "1-2"), device("A", "1-1"), device("C", "2-1")];
+        let assignment = assign(&[rule_at("arm", None, Some("1-*"))], &devices);
+
+        let [ambiguity] = assignment.ambiguous.as_slice() else {
+            panic!("unexpected ambiguities {:?}", assignment.ambiguous);
+        };
+        assert_eq!(ambiguity.role, "arm");
+        assert!(!ambiguity.refused);
+        let candidates: Vec<&str> = ambiguity
+            .candidates
+            .iter()
+            .map(|d| d.port_path.as_str())
+            .collect();
+        assert_eq!(candidates, ["1-1", "1-2"]);
+        assert!(assignment.is_ambiguous("arm"));
+
+        // By default the tie-break still binds the first candidate
+        // 默认情况下仍按平局规则绑定第一个候选
+        assert_eq!(bound(&assignment), [("arm", "1-1")]);
+
+        // A stronger candidate is no ambiguity
+        // 存在更强的候选时不算歧义
+        let rules = [rule_at("arm", Some("A"), Some("1-*"))];
+        let assignment = assign(&rules, &devices);
+        assert!(assignment.ambiguous.is_empty());
+        assert_eq!(bound(&assignment), [("arm", "1-1")]);
+    }
+
+    #[test]
+    fn refuse_policy_leaves_an_ambiguous_role_unbound() {
+        let refusing = DeviceRule {
+            on_ambiguous: AmbiguityPolicy::Refuse,
+            ..rule_at("arm", None, Some("1-*"))
+        };
+        let devices = [device("A", "1-1"), device("B", "1-2")];
+        let assignment = assign(std::slice::from_ref(&refusing), &devices);
+
+        assert!(assignment.bound.is_empty());
+        assert!(assignment.ambiguous[0].refused);
+        assert_eq!(assignment.rejected.len(), 2);
+        assert!(
+            assignment
+                .rejected
+                .iter()
+                .all(|r| matches!(r.reason, RejectReason::Ambiguous))
+        );
+        assert_eq!(
+            assignment.rejected[0].to_string(),
+            "role 'arm' was not bound to /sys/devices/1-1 (PortPathPattern): \
+             several devices match equally well"
+        );
+
+        // Once only one candidate is left the role is bound
+        // 只剩一个候选时角色正常绑定
+        let assignment = assign(&[refusing], &devices[1..]);
+        assert!(assignment.ambiguous.is_empty());
+        assert_eq!(bound(&assignment), [("arm", "1-2")]);
+    }
+}
@@ -0,0 +1,845 @@
+// Rule configuration helpers
+// 规则配置相关的辅助功能
+//
+// VID/PID in the config file may be written as:
+//     6790        -> decimal integer (the historical format)
+//     "0x1a86"    -> hex string with prefix
+//     "1a86"      -> hex string without prefix, exactly as printed by `lsusb`
+// Strings are always hex, integers are always decimal.
+// 配置文件中的 VID/PID 可以写成:
+//     6790        -> 十进制整数 (旧格式)
+//     "0x1a86"    -> 带前缀的十六进制字符串
+//     "1a86"      -> 不带前缀的十六进制字符串, 与 `lsusb` 输出一致
+// 字符串一律按十六进制解析, 整数一律按十进制解析。
+//
+// `pid` additionally accepts a list or an inclusive range:
+//     [21971, "0x55d4"]
+//     { "min": "0x55d0", "max": "0x55df" }
+// `pid` 还可以写成列表或闭区间。
+//
+// The rule file itself may be JSON (with `//` and `/* */` comments), TOML (a `[[rules]]` array
+// of tables) or YAML (a top-level list), see `RuleSet`. TOML and YAML have native hex integers,
+// so an unquoted `0x1a86` works there as well.
+// 规则文件本身可以是 JSON (允许 `//` 和 `/* */` 注释)、TOML (`[[rules]]` 表数组) 或
+// YAML (顶层列表), 见 `RuleSet`。TOML 和 YAML 原生支持十六进制整数, 不加引号的 `0x1a86` 同样可用。
+
+use std::{fmt, fs, path::Path};
+
+use anyhow::Context;
+
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer, de,
+    de::DeserializeOwned,
+    ser::{SerializeMap, SerializeSeq},
+};
+use serde_yaml::Value;
+
+use crate::{DeviceRule, validate::ConfigDiagnostic};
+
+/// VID/PID 的序列化格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdFormat {
+    /// 十进制整数, 如 6790
+    Decimal,
+    /// 带 0x 前缀的小写十六进制字符串, 如 "0x1a86"
+    #[default]
+    Hex,
+}
+
+/// 规则文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// 根据扩展名判断格式 (.json / .toml / .yaml / .yml)
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let ext = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "json" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// 根据内容猜测格式 (扩展名无法判断时使用)
+    pub fn detect(content: &str) -> Self {
+        // Look at the first line that is neither blank nor a comment
+        // 查看第一个既不是空行也不是注释的行
+        let line = content
+            .lines()
+            .map(str::trim)
+            .find(|l| !l.is_empty() && !l.starts_with('#') && !l.starts_with("//"))
+            .unwrap_or("");
+
+        if line.starts_with("[[") {
+            ConfigFormat::Toml
+        } else if line.starts_with('[') || line.starts_with('{') || line.starts_with("/*") {
+            ConfigFormat::Json
+        } else if line
+            .find('=')
+            .is_some_and(|eq| line.find(':').is_none_or(|colon| eq < colon))
+        {
+            ConfigFormat::Toml
+        } else {
+            ConfigFormat::Yaml
+        }
+    }
+}
+
+// TOML needs a table at the top level
+// TOML 的顶层必须是表
+#[derive(Serialize, Deserialize)]
+struct TomlRules<R> {
+    #[serde(default = "Vec::new")]
+    rules: Vec<R>,
+}
+
+// Just the vid of a rule, to find out how the file spells its ids
+// 只读取规则的 vid, 用来判断文件中 id 的写法
+#[derive(Deserialize)]
+struct IdSpelling {
+    #[serde(default)]
+    vid: Option<Value>,
+}
+
+// The first rule's vid in TOML, with its position in the file
+// TOML 中第一条规则的 vid, 以及它在文件中的位置
+#[derive(Deserialize)]
+struct TomlVid {
+    #[serde(default)]
+    vid: Option<toml::Spanned<toml::Value>>,
+}
+
+// Fields of a rule that hold USB ids or class codes
+// 规则中保存 USB id 或类代码的字段
+const ID_FIELDS: [&str; 5] = [
+    "vid",
+    "pid",
+    "bcd_device",
+    "device_class",
+    "interface_class",
+];
+
+/// 从文件加载的规则集, 记住了文件格式以便原样写回
+#[derive(Debug, Clone)]
+pub struct RuleSet {
+    pub rules: Vec<DeviceRule>,
+    pub format: ConfigFormat,
+    /// 保存时 VID/PID 的写法 (解析时取文件中第一个 vid 的写法, 十六进制整数字面量视为 `Hex`)
+    pub id_format: IdFormat,
+}
+
+impl RuleSet {
+    pub fn new(rules: Vec<DeviceRule>, format: ConfigFormat) -> Self {
+        Self {
+            rules,
+            format,
+            id_format: IdFormat::default(),
+        }
+    }
+
+    /// 读取规则文件, 按扩展名 (或内容) 判断格式
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let format =
+            ConfigFormat::from_path(path).unwrap_or_else(|| ConfigFormat::detect(&content));
+        Self::parse(&content, format).with_context(|| path.display().to_string())
+    }
+
+    /// 按指定格式解析, 失败时返回带行号/列号的诊断
+    pub fn parse(content: &str, format: ConfigFormat) -> Result<Self, ConfigDiagnostic> {
+        let rules = parse_list::<DeviceRule>(content, format)?;
+        Ok(Self {
+            rules,
+            format,
+            id_format: id_format_of(content, format),
+        })
+    }
+
+    /// 序列化为原来的格式 (JSON 中的注释不会保留), VID/PID 按 `id_format` 书写
+    pub fn to_config_string(&self) -> anyhow::Result<String> {
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| rule_value(rule, self.id_format))
+            .collect::<Result<Vec<Value>, _>>()?;
+
+        Ok(match self.format {
+            ConfigFormat::Json => serde_json::to_string_pretty(&rules)? + "\n",
+            ConfigFormat::Toml => toml::to_string(&TomlRules {
+                rules: rules.into_iter().map(without_nulls).collect(),
+            })?,
+            ConfigFormat::Yaml => serde_yaml::to_string(&rules)?,
+        })
+    }
+
+    /// 以原来的格式写回文件
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_config_string()?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// 校验规则集, 见 `validate_rules`
+    pub fn validate(&self) -> Vec<ConfigDiagnostic> {
+        crate::validate_rules(&self.rules)
+    }
+}
+
+// Parse a list in any of the config formats
+// 以任意一种配置格式解析一个列表
+fn parse_list<T: DeserializeOwned>(
+    content: &str,
+    format: ConfigFormat,
+) -> Result<Vec<T>, ConfigDiagnostic> {
+    match format {
+        ConfigFormat::Json => parse_json(&strip_json_comments(content)),
+        ConfigFormat::Toml => toml::from_str::<TomlRules<T>>(content)
+            .map(|t| t.rules)
+            .map_err(|e| {
+                let (line, column) = e
+                    .span()
+                    .map_or((0, 0), |span| line_column(content, span.start));
+                ConfigDiagnostic::parse(line, column, e.message().trim())
+            }),
+        ConfigFormat::Yaml => serde_yaml::from_str(content).map_err(|e| {
+            let (line, column) = e.location().map_or((0, 0), |l| (l.line(), l.column()));
+            ConfigDiagnostic::parse(line, column, strip_position(&e.to_string()))
+        }),
+    }
+}
+
+// Saving keeps the spelling of the first rule's vid. A TOML or YAML `0x1a86` is already a number
+// once parsed, so integers are told apart by their text in the file.
+// 保存时沿用第一条规则中 vid 的写法。TOML 或 YAML 中的 `0x1a86` 解析后已经是数字, 因此整数要看它
+// 在文件中的原文。
+fn id_format_of(content: &str, format: ConfigFormat) -> IdFormat {
+    let vid = parse_list::<IdSpelling>(content, format)
+        .ok()
+        .and_then(|list| list.into_iter().next()?.vid);
+    let hex_literal = || {
+        vid_literal(content, format).is_some_and(|text| {
+            let text = text.trim();
+            text.starts_with("0x") || text.starts_with("0X")
+        })
+    };
+    match vid {
+        Some(Value::Number(_)) if !hex_literal() => IdFormat::Decimal,
+        _ => IdFormat::Hex,
+    }
+}
+
+// The text of the first rule's vid in the file (JSON has no hex literals)
+// 文件中第一条规则的 vid 的原文 (JSON 没有十六进制字面量)
+fn vid_literal(content: &str, format: ConfigFormat) -> Option<&str> {
+    match format {
+        ConfigFormat::Json => None,
+        ConfigFormat::Toml => {
+            let rules = toml::from_str::<TomlRules<TomlVid>>(content).ok()?.rules;
+            content.get(rules.into_iter().next()?.vid?.span())
+        }
+        ConfigFormat::Yaml => yaml_vid_literal(content),
+    }
+}
+
+// serde_yaml has no spans: find the first `vid:` key of a block or flow mapping by hand
+// serde_yaml 不提供位置信息: 手动查找块或流式映射中的第一个 `vid:` 键
+fn yaml_vid_literal(content: &str) -> Option<&str> {
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .find_map(|line| {
+            line.match_indices("vid").find_map(|(at, _)| {
+                let before = line[..at].trim_end_matches(['"', '\'']);
+                let key_start = before
+                    .chars()
+                    .next_back()
+                    .is_none_or(|c| c.is_whitespace() || matches!(c, '-' | '{' | ','));
+                let rest = line[at + 3..].trim_start_matches(['"', '\'']).trim_start();
+                let value = rest.strip_prefix(':').filter(|_| key_start)?;
+                let end = value.find([',', '}', '#']).unwrap_or(value.len());
+                Some(value[..end].trim())
+            })
+        })
+}
+
+// A rule as a generic document (field order kept), ids written in `format`
+// 规则转换为通用文档 (保持字段顺序), id 按 `format` 书写
+fn rule_value(rule: &DeviceRule, format: IdFormat) -> Result<Value, serde_yaml::Error> {
+    let mut value = serde_yaml::to_value(rule)?;
+    if format == IdFormat::Decimal
+        && let Value::Mapping(map) = &mut value
+    {
+        for field in ID_FIELDS {
+            if let Some(id) = map.get_mut(field) {
+                ids_to_decimal(id);
+            }
+        }
+    }
+    Ok(value)
+}
+
+// TOML has no null, unset fields are left out instead
+// TOML 没有 null, 未设置的字段直接省略
+fn without_nulls(mut value: Value) -> Value {
+    match &mut value {
+        Value::Mapping(map) => {
+            map.retain(|_, v| !v.is_null());
+            for v in map.values_mut() {
+                *v = without_nulls(std::mem::take(v));
+            }
+        }
+        Value::Sequence(list) => {
+            for v in list.iter_mut() {
+                *v = without_nulls(std::mem::take(v));
+            }
+        }
+        _ => {}
+    }
+    value
+}
+
+// Rules serialize their ids as hex strings; rewrite them (and those in lists and ranges) as
+// decimal integers
+// 规则中的 id 序列化为十六进制字符串; 将它们 (以及列表与区间中的) 改写为十进制整数
+fn ids_to_decimal(value: &mut Value) {
+    match value {
+        Value::String(hex) => {
+            if let Ok(id) = parse_usb_id(hex) {
+                *value = Value::Number(id.into());
+            }
+        }
+        Value::Sequence(list) => list.iter_mut().for_each(ids_to_decimal),
+        Value::Mapping(range) => range.values_mut().for_each(ids_to_decimal),
+        _ => {}
+    }
+}
+
+// Blank out `//` and `/* */` comments outside of strings. Every removed byte becomes a space
+// (newlines are kept), so line/column numbers in parse errors still point into the original.
+// 将字符串之外的 `//` 和 `/* */` 注释替换为空白。每个被移除的字节都替换为一个空格
+// (换行保留), 因此解析错误中的行号/列号仍然对应原文。
+pub(crate) fn strip_json_comments(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let blank = |out: &mut String, c: char| {
+        if c == '\n' {
+            out.push('\n');
+        } else {
+            out.extend(std::iter::repeat_n(' ', c.len_utf8()));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push(c);
+                while let Some(c) = chars.next() {
+                    out.push(c);
+                    match c {
+                        '\\' => out.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                blank(&mut out, c);
+                while let Some(&c) = chars.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    blank(&mut out, c);
+                    chars.next();
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                blank(&mut out, c);
+                blank(&mut out, chars.next().unwrap_or('*'));
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    blank(&mut out, c);
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// 1-based line/column of a byte offset
+// 字节偏移对应的行号/列号 (从 1 开始)
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+// Parsers append " at line X column Y" themselves, keep only the message
+// 解析器会自行追加 " at line X column Y", 这里只保留错误描述
+fn strip_position(message: &str) -> &str {
+    message.rsplit_once(" at line ").map_or(message, |(m, _)| m)
+}
+
+/// 解析 JSON 格式的规则列表, 失败时返回带行号/列号的诊断
+pub fn parse_rules(content: &str) -> Result<Vec<DeviceRule>, ConfigDiagnostic> {
+    parse_json(content)
+}
+
+fn parse_json<T: DeserializeOwned>(content: &str) -> Result<T, ConfigDiagnostic> {
+    serde_json::from_str(content)
+        .map_err(|e| ConfigDiagnostic::parse(e.line(), e.column(), strip_position(&e.to_string())))
+}
+
+/// 解析一个十六进制的 VID/PID 字符串 ("0x1a86" 或 "1a86")
+pub fn parse_usb_id(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+
+    if digits.is_empty() || digits.len() > 4 {
+        return Err(format!(
+            "invalid USB id '{}': expected 1-4 hex digits such as \"0x1a86\" or \"1a86\"",
+            s
+        ));
+    }
+
+    u16::from_str_radix(digits, 16).map_err(|_| {
+        format!(
+            "invalid USB id '{}': expected 1-4 hex digits such as \"0x1a86\" or \"1a86\"",
+            s
+        )
+    })
+}
+
+/// 规则中的 PID 条件: 单个值、列表或闭区间
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PidMatch {
+    Exact(u16),
+    List(Vec<u16>),
+    Range { min: u16, max: u16 },
+}
+
+impl Default for PidMatch {
+    fn default() -> Self {
+        PidMatch::Exact(0)
+    }
+}
+
+impl From<u16> for PidMatch {
+    fn from(pid: u16) -> Self {
+        PidMatch::Exact(pid)
+    }
+}
+
+impl PidMatch {
+    /// 设备的 PID 是否满足该条件
+    pub fn contains(&self, pid: u16) -> bool {
+        match self {
+            PidMatch::Exact(p) => *p == pid,
+            PidMatch::List(list) => list.contains(&pid),
+            PidMatch::Range { min, max } => (*min..=*max).contains(&pid),
+        }
+    }
+}
+
+// A single id, serialized as a hex string
+// 单个 id, 序列化为十六进制字符串
+struct UsbId(u16);
+
+impl Serialize for UsbId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        usb_id::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for UsbId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        usb_id::deserialize(deserializer).map(UsbId)
+    }
+}
+
+impl Serialize for PidMatch {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            PidMatch::Exact(pid) => UsbId(*pid).serialize(serializer),
+            PidMatch::List(list) => {
+                let mut seq = serializer.serialize_seq(Some(list.len()))?;
+                for pid in list {
+                    seq.serialize_element(&UsbId(*pid))?;
+                }
+                seq.end()
+            }
+            PidMatch::Range { min, max } => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("min", &UsbId(*min))?;
+                map.serialize_entry("max", &UsbId(*max))?;
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PidMatch {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(PidMatchVisitor)
+    }
+}
+
+struct PidMatchVisitor;
+
+impl<'de> de::Visitor<'de> for PidMatchVisitor {
+    type Value = PidMatch;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a PID, a list of PIDs or a {\"min\", \"max\"} range")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<PidMatch, E> {
+        usb_id::UsbIdVisitor.visit_u64(v).map(PidMatch::Exact)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<PidMatch, E> {
+        usb_id::UsbIdVisitor.visit_i64(v).map(PidMatch::Exact)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<PidMatch, E> {
+        usb_id::UsbIdVisitor.visit_str(v).map(PidMatch::Exact)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<PidMatch, A::Error> {
+        let mut list = vec![];
+        while let Some(UsbId(pid)) = seq.next_element()? {
+            list.push(pid);
+        }
+        Ok(PidMatch::List(list))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<PidMatch, A::Error> {
+        let mut min = None;
+        let mut max = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "min" => min = Some(map.next_value::<UsbId>()?.0),
+                "max" => max = Some(map.next_value::<UsbId>()?.0),
+                other => return Err(de::Error::unknown_field(other, &["min", "max"])),
+            }
+        }
+        Ok(PidMatch::Range {
+            min: min.ok_or_else(|| de::Error::missing_field("min"))?,
+            max: max.ok_or_else(|| de::Error::missing_field("max"))?,
+        })
+    }
+}
+
+// serde `with` module for VID/PID fields
+// VID/PID 字段使用的 serde `with` 模块
+pub(crate) mod usb_id {
+    use std::fmt;
+
+    use serde::{Deserializer, Serializer, de};
+
+    use super::parse_usb_id;
+
+    // Rules are written as hex, `RuleSet` rewrites them for decimal files
+    // 规则以十六进制书写, 十进制的文件由 `RuleSet` 负责改写
+    pub fn serialize<S: Serializer>(id: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{:04x}", id))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+        deserializer.deserialize_any(UsbIdVisitor)
+    }
+
+    pub(super) struct UsbIdVisitor;
+
+    impl de::Visitor<'_> for UsbIdVisitor {
+        type Value = u16;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a decimal integer or a hex string such as \"0x1a86\"")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<u16, E> {
+            u16::try_from(v).map_err(|_| E::custom(format!("USB id {} is out of range", v)))
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<u16, E> {
+            u16::try_from(v).map_err(|_| E::custom(format!("USB id {} is out of range", v)))
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<u16, E> {
+            parse_usb_id(v).map_err(E::custom)
+        }
+    }
+}
+
+// serde `with` module for optional 16-bit ids such as bcdDevice
+// 可选的 16 位 id (如 bcdDevice) 使用的 serde `with` 模块
+pub(crate) mod opt_usb_id {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::UsbId;
+
+    pub fn serialize<S: Serializer>(id: &Option<u16>, serializer: S) -> Result<S::Ok, S::Error> {
+        match id {
+            Some(id) => serializer.serialize_some(&UsbId(*id)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u16>, D::Error> {
+        Ok(Option::<UsbId>::deserialize(deserializer)?.map(|id| id.0))
+    }
+}
+
+// serde `with` module for optional 8-bit USB class codes (bDeviceClass, bInterfaceClass)
+// 可选的 8 位 USB 类代码 (bDeviceClass、bInterfaceClass) 使用的 serde `with` 模块
+pub(crate) mod opt_class {
+    use serde::{Deserialize, Deserializer, Serializer, de};
+
+    use super::UsbId;
+
+    pub fn serialize<S: Serializer>(class: &Option<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+        match class {
+            Some(class) => serializer.serialize_str(&format!("0x{:02x}", class)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u8>, D::Error> {
+        match Option::<UsbId>::deserialize(deserializer)? {
+            Some(UsbId(v)) => u8::try_from(v)
+                .map(Some)
+                .map_err(|_| de::Error::custom(format!("USB class {:#x} is out of range", v))),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validate::DiagnosticKind;
+
+    #[test]
+    fn parses_all_id_spellings() {
+        for vid in ["6790", "\"0x1a86\"", "\"0X1A86\"", "\"1a86\""] {
+            let json = format!(r#"{{"role": "arm", "vid": {}, "pid": 21971}}"#, vid);
+            let rule: DeviceRule = serde_json::from_str(&json).unwrap();
+            assert_eq!(rule.vid, 0x1a86, "{}", vid);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_ids() {
+        assert!(parse_usb_id("0x").is_err());
+        assert!(parse_usb_id("1a86f").is_err());
+        assert!(parse_usb_id("zz").is_err());
+        assert!(
+            serde_json::from_str::<DeviceRule>(r#"{"role": "a", "vid": 70000, "pid": 1}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn serializes_in_requested_format() {
+        let rule = DeviceRule {
+            role: "arm".to_string(),
+            vid: 0x1a86,
+            pid: 0x55d3.into(),
+            ..Default::default()
+        };
+
+        let hex = serde_json::to_value(&rule).unwrap();
+        assert_eq!(hex["vid"], "0x1a86");
+        assert_eq!(hex["pid"], "0x55d3");
+
+        let mut set = RuleSet::new(vec![rule], ConfigFormat::Json);
+        set.id_format = IdFormat::Decimal;
+        let dec: serde_json::Value =
+            serde_json::from_str(&set.to_config_string().unwrap()).unwrap();
+        assert_eq!(dec[0]["vid"], 6790);
+        assert_eq!(dec[0]["pid"], 21971);
+        assert_eq!(dec[0]["role"], "arm");
+    }
+
+    #[test]
+    fn pid_lists_and_ranges() {
+        let rule: DeviceRule =
+            serde_json::from_str(r#"{"role": "a", "vid": 6790, "pid": [21971, "0x7523"]}"#)
+                .unwrap();
+        assert_eq!(rule.pid, PidMatch::List(vec![0x55d3, 0x7523]));
+        assert!(rule.pid.contains(0x7523));
+        assert!(!rule.pid.contains(0x7522));
+
+        let rule: DeviceRule = serde_json::from_str(
+            r#"{"role": "a", "vid": 6790, "pid": {"min": "0x55d0", "max": "0x55df"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rule.pid,
+            PidMatch::Range {
+                min: 0x55d0,
+                max: 0x55df
+            }
+        );
+        assert!(rule.pid.contains(0x55d3));
+        assert!(!rule.pid.contains(0x55e0));
+
+        let value = serde_json::to_value(&rule).unwrap();
+        assert_eq!(value["pid"]["min"], "0x55d0");
+    }
+
+    #[test]
+    fn parse_errors_carry_position() {
+        let err = parse_rules("[\n  {\"role\": \"arm\", \"vid\": 6790,}\n]").unwrap_err();
+        match err.kind {
+            DiagnosticKind::Parse { line, column, .. } => assert_eq!((line, column), (2, 31)),
+            other => panic!("unexpected diagnostic {:?}", other),
+        }
+    }
+
+    const JSON: &str = r#"[
+  // The README style: comments are allowed
+  {
+    "role": "arm", /* hex */ "vid": "0x1a86",
+    "pid": "0x55d3",
+    "serial": "http://x//y" // a "//" inside a string is kept
+  }
+]"#;
+
+    const TOML: &str = r#"
+# robot config
+[[rules]]
+role = "arm"
+vid = 0x1a86
+pid = "0x55d3"
+serial = "http://x//y"
+"#;
+
+    const YAML: &str = r#"
+# robot config
+- role: arm
+  vid: 0x1a86
+  pid: "0x55d3"
+  serial: http://x//y
+"#;
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(ConfigFormat::detect(JSON), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::detect(TOML), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::detect(YAML), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::detect("rules = []"), ConfigFormat::Toml);
+        assert_eq!(
+            ConfigFormat::from_path("robot/devices.yml"),
+            Some(ConfigFormat::Yaml)
+        );
+        assert_eq!(ConfigFormat::from_path("devices"), None);
+    }
+
+    #[test]
+    fn all_formats_parse_to_the_same_rules() {
+        for (content, format) in [
+            (JSON, ConfigFormat::Json),
+            (TOML, ConfigFormat::Toml),
+            (YAML, ConfigFormat::Yaml),
+        ] {
+            let set = RuleSet::parse(content, format).unwrap();
+            assert_eq!(set.rules.len(), 1, "{:?}", format);
+            let rule = &set.rules[0];
+            assert_eq!((rule.vid, rule.pid.clone()), (0x1a86, 0x55d3.into()));
+            assert_eq!(rule.serial.as_deref(), Some("http://x//y"), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn round_trips_in_the_same_format() {
+        for format in [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml] {
+            let mut set = RuleSet::new(
+                vec![DeviceRule {
+                    role: "arm".to_string(),
+                    vid: 0x1a86,
+                    pid: PidMatch::Range {
+                        min: 0x55d0,
+                        max: 0x55df,
+                    },
+                    port_path: Some("1-2.*".to_string()),
+                    device_class: Some(0x02),
+                    ..Default::default()
+                }],
+                format,
+            );
+            set.id_format = IdFormat::Decimal;
+
+            let text = set.to_config_string().unwrap();
+            let parsed = RuleSet::parse(&text, ConfigFormat::detect(&text)).unwrap();
+            assert_eq!(parsed.format, format, "{}", text);
+            assert_eq!(parsed.id_format, IdFormat::Decimal, "{}", text);
+            assert_eq!(parsed.rules[0].pid, set.rules[0].pid);
+            assert_eq!(parsed.rules[0].port_path, set.rules[0].port_path);
+            assert_eq!(parsed.rules[0].device_class, Some(0x02));
+        }
+    }
+
+    #[test]
+    fn hex_integer_literals_round_trip_as_hex() {
+        let toml = "[[rules]]\nrole = \"arm\"\nvid = 0x1a86\npid = 0x55D3\n";
+        let yaml = "- role: arm\n  vid: 0x1a86 # CH343\n  pid: 0x55d3\n";
+        for (text, format) in [(toml, ConfigFormat::Toml), (yaml, ConfigFormat::Yaml)] {
+            let set = RuleSet::parse(text, format).unwrap();
+            assert_eq!(set.id_format, IdFormat::Hex, "{}", text);
+            assert_eq!(set.rules[0].vid, 0x1a86);
+
+            let saved = set.to_config_string().unwrap();
+            assert!(saved.contains("0x1a86"), "{}", saved);
+            assert!(!saved.contains("6790"), "{}", saved);
+            let reparsed = RuleSet::parse(&saved, format).unwrap();
+            assert_eq!(reparsed.id_format, IdFormat::Hex, "{}", saved);
+            assert_eq!(reparsed.rules[0].pid, PidMatch::Exact(0x55d3));
+        }
+
+        // Plain integers stay decimal, also in flow style
+        // 普通整数仍为十进制, 流式写法同样如此
+        let yaml = "- {role: arm, vid: 6790, pid: 21971}\n";
+        let set = RuleSet::parse(yaml, ConfigFormat::Yaml).unwrap();
+        assert_eq!(set.id_format, IdFormat::Decimal);
+    }
+
+    #[test]
+    fn comment_stripping_keeps_positions() {
+        let content = "[ /* 注释 */ {\"role\": \"arm\", \"vid\": 6790,}\n]";
+        let err = RuleSet::parse(content, ConfigFormat::Json).unwrap_err();
+        match err.kind {
+            DiagnosticKind::Parse { line, column, .. } => {
+                assert_eq!((line, column), (1, content.find(",}").unwrap() + 2))
+            }
+            other => panic!("unexpected diagnostic {:?}", other),
+        }
+
+        let err =
+            RuleSet::parse("[[rules]]\nrole = \"arm\"\nvid = \n", ConfigFormat::Toml).unwrap_err();
+        assert!(
+            matches!(err.kind, DiagnosticKind::Parse { line: 3, .. }),
+            "{}",
+            err
+        );
+    }
+}
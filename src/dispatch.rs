@@ -0,0 +1,453 @@
+// Callback API: register closures for role events instead of owning a receiver loop
+// 回调接口: 为角色事件注册闭包, 而不必自己维护一个接收循环
+//
+// A `Dispatcher` runs a `Resolver` and calls the registered callbacks from one background
+// thread, in event order. Each registration takes a filter (role, VID/PID or path, with the
+// same glob patterns as the rules) and returns a `Subscription`; dropping it unregisters the
+// callback. A callback registered late is first called for the roles that are already bound,
+// so subscribers do not depend on when they were registered.
+// `Dispatcher` 运行一个 `Resolver`, 在一个后台线程中按事件顺序调用已注册的回调。每次注册都带有
+// 一个过滤条件 (角色、VID/PID 或路径, 支持与规则相同的通配符), 并返回一个 `Subscription`;
+// drop 它即可取消注册。较晚注册的回调会先收到已经绑定的角色, 因此订阅者不依赖注册的时机。
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use anyhow::Result;
+use crossbeam_channel::Sender;
+
+use crate::{
+    MonitorError, MonitorHandle, MonitorHealth, RawDeviceInfo, ResolvedDevice, Resolver, RoleEvent,
+    RoleId, pattern::match_field,
+};
+
+/// 订阅的过滤条件, 设置了的条件都要满足
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    /// 角色 (支持通配符)
+    pub role: Option<String>,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    /// system_path、port_path、设备节点或其符号链接之一 (支持通配符, 例如 "/dev/ttyACM*")
+    pub path: Option<String>,
+}
+
+impl EventFilter {
+    /// 不过滤
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    /// 按角色过滤
+    pub fn role(role: impl Into<String>) -> Self {
+        Self {
+            role: Some(role.into()),
+            ..Default::default()
+        }
+    }
+
+    /// 按 VID/PID 过滤
+    pub fn usb_id(vid: u16, pid: u16) -> Self {
+        Self {
+            vid: Some(vid),
+            pid: Some(pid),
+            ..Default::default()
+        }
+    }
+
+    /// 按路径过滤
+    pub fn path(path: impl Into<String>) -> Self {
+        Self {
+            path: Some(path.into()),
+            ..Default::default()
+        }
+    }
+
+    /// 角色 `role` 上的设备 `device` 是否满足条件
+    pub fn matches(&self, role: &str, device: &RawDeviceInfo) -> bool {
+        if let Some(pattern) = &self.role
+            && match_field(pattern, role).is_none()
+        {
+            return false;
+        }
+        if self.vid.is_some_and(|vid| vid != device.vid)
+            || self.pid.is_some_and(|pid| pid != device.pid)
+        {
+            return false;
+        }
+        let Some(pattern) = &self.path else {
+            return true;
+        };
+
+        let nodes = device
+            .children
+            .iter()
+            .flat_map(|c| std::iter::once(&c.devnode).chain(&c.links));
+        [&device.system_path, &device.port_path]
+            .into_iter()
+            .chain(&device.system_path_alt)
+            .chain(nodes)
+            .any(|path| match_field(pattern, path).is_some())
+    }
+}
+
+// `on_attached("arm", ...)` filters by role
+// `on_attached("arm", ...)` 按角色过滤
+impl From<&str> for EventFilter {
+    fn from(role: &str) -> Self {
+        Self::role(role)
+    }
+}
+
+impl From<String> for EventFilter {
+    fn from(role: String) -> Self {
+        Self::role(role)
+    }
+}
+
+type DeviceFn = Box<dyn Fn(&ResolvedDevice) + Send>;
+type DetachedFn = Box<dyn Fn(&str, &RawDeviceInfo) + Send>;
+
+enum Callback {
+    Attached(DeviceFn),
+    Detached(DetachedFn),
+    Updated(DeviceFn),
+    Event(Box<dyn Fn(&RoleEvent) + Send>),
+}
+
+// A registered callback, owned by the dispatch thread
+// 已注册的回调, 归分发线程所有
+struct Entry {
+    filter: EventFilter,
+    callback: Callback,
+    active: Arc<AtomicBool>,
+}
+
+impl Entry {
+    fn attached(&self, device: &ResolvedDevice) {
+        if let Callback::Attached(f) = &self.callback
+            && self.filter.matches(&device.role, &device.device)
+        {
+            f(device);
+        }
+    }
+
+    fn detached(&self, role: &str, device: &RawDeviceInfo) {
+        if let Callback::Detached(f) = &self.callback
+            && self.filter.matches(role, device)
+        {
+            f(role, device);
+        }
+    }
+
+    fn updated(&self, device: &ResolvedDevice) {
+        if let Callback::Updated(f) = &self.callback
+            && self.filter.matches(&device.role, &device.device)
+        {
+            f(device);
+        }
+    }
+
+    // Checked before every call, so a callback dropped by another callback is not called again
+    // 每次调用前都检查, 因此被另一个回调 drop 的订阅不会再被调用
+    fn dispatch(&self, event: &RoleEvent) {
+        if !self.active.load(Ordering::Acquire) {
+            return;
+        }
+        match event {
+            RoleEvent::RoleAttached(device) => self.attached(device),
+            RoleEvent::RoleDetached { role, last_device } => self.detached(role, last_device),
+            RoleEvent::RoleUpdated(device) => self.updated(device),
+            // A rebind is a detach of the previous device followed by an attach
+            // 换绑相当于先移除旧设备, 再绑定新设备
+            RoleEvent::RoleRebound {
+                role,
+                previous,
+                current,
+            } => {
+                self.detached(role, previous);
+                self.attached(current);
+            }
+            _ => {}
+        }
+        if let Callback::Event(f) = &self.callback {
+            f(event);
+        }
+    }
+}
+
+// Add a callback, replaying the current bindings to it first
+// 添加回调, 先向它补发当前的绑定
+fn register(entry: Entry, bound: &BTreeMap<RoleId, ResolvedDevice>, registered: &mut Vec<Entry>) {
+    for device in bound.values() {
+        entry.attached(device);
+    }
+    registered.push(entry);
+}
+
+/// 订阅句柄, 被 drop 时取消订阅
+#[must_use = "the callback is unregistered as soon as the subscription is dropped"]
+pub struct Subscription {
+    active: Arc<AtomicBool>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.active.store(false, Ordering::Release);
+    }
+}
+
+/// 回调分发器: 运行解析器, 并把角色事件分发给已注册的回调
+/// 回调在同一个后台线程中依次调用, 不应长时间阻塞; 被 drop 时解析器随之停止
+pub struct Dispatcher {
+    entries: Sender<Entry>,
+    handle: MonitorHandle,
+}
+
+impl Dispatcher {
+    /// 启动 `resolver`, 在后台线程中分发它的事件 (`resolver` 已在运行时返回错误)
+    pub fn start(resolver: &Resolver) -> Result<Self> {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let resolver = resolver.start(tx)?;
+        let (entries, entry_rx) = crossbeam_channel::unbounded::<Entry>();
+
+        let handle = MonitorHandle::spawn("role-dispatcher", move |stop| {
+            // Stopping the dispatcher drops `resolver`, which stops the resolver too
+            // 分发线程退出时会 drop `resolver`, 解析器随之停止
+            let resolver = resolver;
+            let (stop_tx, stop_rx) = crossbeam_channel::bounded(1);
+            stop.on_stop(move || {
+                let _ = stop_tx.try_send(());
+            });
+
+            let mut registered: Vec<Entry> = vec![];
+            // What is bound right now, replayed to late on_attached subscribers
+            // 当前的绑定, 用于补发给较晚注册的 on_attached 回调
+            let mut bound: BTreeMap<RoleId, ResolvedDevice> = BTreeMap::new();
+
+            loop {
+                crossbeam_channel::select! {
+                    recv(stop_rx) -> _ => return,
+                    recv(entry_rx) -> entry => match entry {
+                        Ok(entry) => register(entry, &bound, &mut registered),
+                        Err(_) => return,
+                    },
+                    recv(rx) -> event => match event {
+                        Ok(event) => {
+                            // Callbacks registered before the event happened must see it
+                            // 在事件发生之前注册的回调必须收到它
+                            while let Ok(entry) = entry_rx.try_recv() {
+                                register(entry, &bound, &mut registered);
+                            }
+                            match &event {
+                                RoleEvent::RoleAttached(device) | RoleEvent::RoleUpdated(device) => {
+                                    bound.insert(device.role.clone(), device.clone());
+                                }
+                                RoleEvent::RoleRebound { current, .. } => {
+                                    bound.insert(current.role.clone(), current.clone());
+                                }
+                                RoleEvent::RoleDetached { role, .. } => {
+                                    bound.remove(role);
+                                }
+                                RoleEvent::MonitorError(error) => stop.report_error(error.clone()),
+                                RoleEvent::MonitorRecovered => stop.report_ok(),
+                                _ => {}
+                            }
+
+                            registered.retain(|entry| entry.active.load(Ordering::Acquire));
+                            for entry in &registered {
+                                entry.dispatch(&event);
+                            }
+                        }
+                        // The resolver thread has exited
+                        // 解析线程已退出
+                        Err(_) => {
+                            match resolver.health() {
+                                MonitorHealth::Stopped(Some(error)) => stop.report_error(error),
+                                _ => stop.report_error(MonitorError::ChannelClosed),
+                            }
+                            return;
+                        }
+                    },
+                }
+            }
+        })?;
+
+        Ok(Self { entries, handle })
+    }
+
+    fn register(&self, filter: EventFilter, callback: Callback) -> Subscription {
+        let active = Arc::new(AtomicBool::new(true));
+        let _ = self.entries.send(Entry {
+            filter,
+            callback,
+            active: Arc::clone(&active),
+        });
+        Subscription { active }
+    }
+
+    /// 角色绑定到设备时调用 (包括换绑后的新设备, 以及注册时已经绑定的角色)
+    pub fn on_attached(
+        &self,
+        filter: impl Into<EventFilter>,
+        f: impl Fn(&ResolvedDevice) + Send + 'static,
+    ) -> Subscription {
+        self.register(filter.into(), Callback::Attached(Box::new(f)))
+    }
+
+    /// 角色失去设备时调用, 参数为角色与最后绑定的设备 (包括换绑前的旧设备)
+    pub fn on_detached(
+        &self,
+        filter: impl Into<EventFilter>,
+        f: impl Fn(&str, &RawDeviceInfo) + Send + 'static,
+    ) -> Subscription {
+        self.register(filter.into(), Callback::Detached(Box::new(f)))
+    }
+
+    /// 已绑定设备的信息更新时调用 (例如 tty 节点稍后出现)
+    pub fn on_updated(
+        &self,
+        filter: impl Into<EventFilter>,
+        f: impl Fn(&ResolvedDevice) + Send + 'static,
+    ) -> Subscription {
+        self.register(filter.into(), Callback::Updated(Box::new(f)))
+    }
+
+    /// 接收所有角色事件 (包括歧义、规则重载与监听器故障), 不过滤
+    pub fn on_event(&self, f: impl Fn(&RoleEvent) + Send + 'static) -> Subscription {
+        self.register(EventFilter::any(), Callback::Event(Box::new(f)))
+    }
+
+    /// 当前的健康状态 (与解析器一致)
+    pub fn health(&self) -> MonitorHealth {
+        self.handle.health()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        ChildNode, NodeKind, SimulatedMonitor,
+        test_support::{device, rule},
+    };
+
+    fn recv(rx: &crossbeam_channel::Receiver<String>) -> String {
+        rx.recv_timeout(Duration::from_secs(5)).unwrap()
+    }
+
+    // A device with its tty node, as the Linux monitor reports it
+    // 带 tty 节点的设备, 与 Linux 监听器上报的一致
+    fn with_tty(serial: &str, port: &str) -> RawDeviceInfo {
+        let tty = format!("/dev/tty{}", serial);
+        RawDeviceInfo {
+            system_path_alt: Some(tty.clone()),
+            children: vec![ChildNode {
+                subsystem: "tty".to_string(),
+                devnode: tty,
+                kind: NodeKind::Serial,
+                interface_number: Some(0),
+                links: vec![format!(
+                    "/dev/serial/by-id/usb-1a86_USB_Single_Serial_{}-if00",
+                    serial
+                )],
+            }],
+            ..device(serial, port)
+        }
+    }
+
+    #[test]
+    fn path_filter_matches_nodes_and_links() {
+        let dev = with_tty("B", "1-2");
+        for pattern in [
+            "/dev/ttyB",
+            "/dev/tty*",
+            "/dev/serial/by-id/*_B-if00",
+            "/sys/devices/1-2",
+            "1-2",
+        ] {
+            assert!(
+                EventFilter::path(pattern).matches("arm", &dev),
+                "{}",
+                pattern
+            );
+        }
+        for pattern in ["/dev/ttyA", "/dev/serial/by-id/*_A-if00", "/dev/video*"] {
+            assert!(
+                !EventFilter::path(pattern).matches("arm", &dev),
+                "{}",
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn callbacks_follow_their_filters() {
+        let monitor = SimulatedMonitor::new();
+        let resolver = Resolver::with_monitor(
+            vec![rule("arm", "A"), rule("gripper", "B")],
+            Box::new(monitor.clone()),
+        );
+        monitor.plug(with_tty("A", "1-1"));
+        monitor.plug(with_tty("B", "1-2"));
+        let dispatcher = Dispatcher::start(&resolver).unwrap();
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        let arm_tx = tx.clone();
+        let _arm = dispatcher.on_attached("arm", move |dev| {
+            arm_tx.send(format!("arm: {}", dev.role)).unwrap()
+        });
+        let path_tx = tx.clone();
+        let _path = dispatcher.on_detached(EventFilter::path("/dev/ttyB"), move |role, _| {
+            path_tx.send(format!("path: -{}", role)).unwrap()
+        });
+        let _other = dispatcher.on_attached(EventFilter::usb_id(0x0403, 0x6001), move |dev| {
+            tx.send(format!("ftdi: {}", dev.role)).unwrap()
+        });
+
+        assert_eq!(recv(&rx), "arm: arm");
+        monitor.unplug("/sys/devices/1-2");
+        monitor.unplug("/sys/devices/1-1");
+        assert_eq!(recv(&rx), "path: -gripper");
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn late_subscribers_see_bound_roles_and_drop_unsubscribes() {
+        let monitor = SimulatedMonitor::new();
+        let resolver = Resolver::with_monitor(
+            vec![rule("arm", "A"), rule("gripper", "B")],
+            Box::new(monitor.clone()),
+        );
+        let dispatcher = Dispatcher::start(&resolver).unwrap();
+
+        // Wait until the arm is bound, then subscribe
+        // 等到 arm 绑定之后再订阅
+        let (seen_tx, seen_rx) = crossbeam_channel::unbounded();
+        let _witness = dispatcher.on_event(move |event| {
+            if let RoleEvent::RoleAttached(dev) = event {
+                seen_tx.send(dev.role.clone()).unwrap();
+            }
+        });
+        monitor.plug(device("A", "1-1"));
+        assert_eq!(recv(&seen_rx), "arm");
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let subscription = dispatcher.on_attached(EventFilter::any(), move |dev| {
+            tx.send(dev.role.clone()).unwrap()
+        });
+        assert_eq!(recv(&rx), "arm");
+
+        drop(subscription);
+        monitor.plug(device("B", "1-2"));
+        assert_eq!(recv(&seen_rx), "gripper");
+        assert!(rx.try_recv().is_err());
+    }
+}
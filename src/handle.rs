@@ -0,0 +1,282 @@
+// Lifetime control for monitor background threads
+// 监听器后台线程的生命周期控制
+//
+// `DeviceMonitor::start` returns a `MonitorHandle`. Dropping the handle (or calling `stop`)
+// asks the thread to finish and waits for it, so monitors can be torn down and recreated
+// inside a long-running process.
+// `DeviceMonitor::start` 返回一个 `MonitorHandle`。drop 该句柄 (或调用 `stop`) 会通知线程
+// 结束并等待它退出, 因此可以在长期运行的进程中销毁并重新创建监听器。
+//
+// Failures are reported twice: as `DeviceEvent::MonitorError` on the event channel, and as the
+// current `MonitorHealth` of the handle, for applications that would rather poll.
+// 故障会通过两种方式上报: 事件 channel 上的 `DeviceEvent::MonitorError`, 以及句柄当前的
+// `MonitorHealth` (适合更愿意主动查询的应用)。
+
+use std::{
+    error::Error,
+    fmt, io,
+    sync::{Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+type Waker = Box<dyn Fn() + Send + Sync>;
+
+/// 监听器故障
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MonitorError {
+    /// 没有访问设备事件接口的权限 (例如容器中没有 netlink 权限)
+    PermissionDenied(String),
+    /// 与系统的事件连接建立失败或中断
+    Socket(String),
+    /// 枚举当前设备失败
+    Enumeration(String),
+    /// 事件接收端已关闭, 监听线程已退出
+    ChannelClosed,
+}
+
+impl MonitorError {
+    /// 将 I/O 错误归类为 `PermissionDenied` 或 `Socket`
+    pub fn from_io(context: &str, e: &io::Error) -> Self {
+        let message = format!("{}: {}", context, e);
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => MonitorError::PermissionDenied(message),
+            _ => MonitorError::Socket(message),
+        }
+    }
+}
+
+impl fmt::Display for MonitorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MonitorError::PermissionDenied(message) => {
+                write!(f, "permission denied: {}", message)
+            }
+            MonitorError::Socket(message) => write!(f, "event socket failed: {}", message),
+            MonitorError::Enumeration(message) => write!(f, "enumeration failed: {}", message),
+            MonitorError::ChannelClosed => write!(f, "the event receiver has been dropped"),
+        }
+    }
+}
+
+impl Error for MonitorError {}
+
+/// 监听器的健康状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorHealth {
+    /// 正常运行
+    Running,
+    /// 出现故障, 线程仍在运行并自动重试
+    Degraded(MonitorError),
+    /// 线程已退出 (正常停止时为 None)
+    Stopped(Option<MonitorError>),
+}
+
+/// 后台线程的停止信号
+///
+/// 线程通过 `is_stopped` / `wait_timeout` 检查信号; 阻塞在系统调用上的线程可以用 `on_stop`
+/// 注册一个唤醒函数。
+pub struct StopSignal {
+    stopped: Mutex<bool>,
+    condvar: Condvar,
+    wakers: Mutex<Vec<Waker>>,
+    health: Mutex<MonitorHealth>,
+}
+
+impl Default for StopSignal {
+    fn default() -> Self {
+        Self {
+            stopped: Mutex::default(),
+            condvar: Condvar::new(),
+            wakers: Mutex::default(),
+            health: Mutex::new(MonitorHealth::Running),
+        }
+    }
+}
+
+impl StopSignal {
+    /// 线程上报故障 (句柄的 `health` 变为 `Degraded`, 线程退出后变为 `Stopped(Some(..))`)
+    pub fn report_error(&self, error: MonitorError) {
+        *self.health.lock().unwrap() = MonitorHealth::Degraded(error);
+    }
+
+    /// 线程上报故障已恢复
+    pub fn report_ok(&self) {
+        *self.health.lock().unwrap() = MonitorHealth::Running;
+    }
+
+    /// 是否已经请求停止
+    pub fn is_stopped(&self) -> bool {
+        *self.stopped.lock().unwrap()
+    }
+
+    /// 最多等待 `timeout`, 期间收到停止请求会立即返回。返回值表示是否已经请求停止
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let stopped = self.stopped.lock().unwrap();
+        let (stopped, _) = self
+            .condvar
+            .wait_timeout_while(stopped, timeout, |stopped| !*stopped)
+            .unwrap();
+        *stopped
+    }
+
+    /// 注册停止时调用的唤醒函数 (已经停止时立即调用)
+    pub fn on_stop(&self, waker: impl Fn() + Send + Sync + 'static) {
+        // `stop` flips the flag while holding the waker list, so a waker is never missed
+        // `stop` 在持有唤醒函数列表的锁时修改标志, 因此不会漏掉唤醒函数
+        let mut wakers = self.wakers.lock().unwrap();
+        if self.is_stopped() {
+            waker();
+            return;
+        }
+        wakers.push(Box::new(waker));
+    }
+
+    fn stop(&self) {
+        let wakers = self.wakers.lock().unwrap();
+        {
+            let mut stopped = self.stopped.lock().unwrap();
+            if *stopped {
+                return;
+            }
+            *stopped = true;
+        }
+        self.condvar.notify_all();
+        for waker in wakers.iter() {
+            waker();
+        }
+    }
+}
+
+/// 监听器后台线程的句柄, 被 drop 时停止线程并等待其退出
+pub struct MonitorHandle {
+    signal: Arc<StopSignal>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MonitorHandle {
+    /// 启动一个后台线程, 线程函数收到停止信号, 应在信号触发后尽快返回
+    pub fn spawn<F>(name: &str, f: F) -> std::io::Result<Self>
+    where
+        F: FnOnce(Arc<StopSignal>) + Send + 'static,
+    {
+        let signal = Arc::new(StopSignal::default());
+        let thread_signal = Arc::clone(&signal);
+        let thread = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || f(thread_signal))?;
+
+        Ok(Self {
+            signal,
+            thread: Some(thread),
+        })
+    }
+
+    /// 请求后台线程停止 (不等待)
+    pub fn stop(&self) {
+        self.signal.stop();
+    }
+
+    /// 等待后台线程退出 (不会主动请求停止)
+    pub fn join(mut self) -> thread::Result<()> {
+        match self.thread.take() {
+            Some(thread) => thread.join(),
+            None => Ok(()),
+        }
+    }
+
+    /// 后台线程是否仍在运行
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|t| !t.is_finished())
+    }
+
+    /// 当前的健康状态
+    pub fn health(&self) -> MonitorHealth {
+        let health = self.signal.health.lock().unwrap().clone();
+        if self.is_running() {
+            return health;
+        }
+        match health {
+            MonitorHealth::Running => MonitorHealth::Stopped(None),
+            MonitorHealth::Degraded(error) => MonitorHealth::Stopped(Some(error)),
+            stopped => stopped,
+        }
+    }
+}
+
+impl Drop for MonitorHandle {
+    fn drop(&mut self) {
+        self.signal.stop();
+        if let Some(thread) = self.thread.take() {
+            // Dropped from its own thread (e.g. by a callback), joining would deadlock
+            // 在线程自身中被 drop (例如在回调里), 此时 join 会死锁
+            if thread.thread().id() != thread::current().id() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn drop_stops_and_joins() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let handle = MonitorHandle::spawn("test-monitor", move |signal| {
+            while !signal.wait_timeout(Duration::from_secs(10)) {}
+            tx.send(()).unwrap();
+        })
+        .unwrap();
+        assert!(handle.is_running());
+
+        let started = Instant::now();
+        drop(handle);
+        assert!(rx.try_recv().is_ok());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn health_follows_reports_and_exit() {
+        let (tx, rx) = crossbeam_channel::bounded::<()>(0);
+        let handle = MonitorHandle::spawn("test-monitor", move |signal| {
+            signal.report_error(MonitorError::Socket("down".to_string()));
+            let _ = tx.send(());
+            let _ = tx.send(());
+            signal.report_error(MonitorError::ChannelClosed);
+        })
+        .unwrap();
+
+        rx.recv().unwrap();
+        assert_eq!(
+            handle.health(),
+            MonitorHealth::Degraded(MonitorError::Socket("down".to_string()))
+        );
+
+        drop(rx);
+        while handle.is_running() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(
+            handle.health(),
+            MonitorHealth::Stopped(Some(MonitorError::ChannelClosed))
+        );
+    }
+
+    #[test]
+    fn wakers_run_once_on_stop() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let handle = MonitorHandle::spawn("test-monitor", |_| {}).unwrap();
+        handle.signal.on_stop(move || tx.send(()).unwrap());
+
+        handle.stop();
+        handle.stop();
+        handle.join().unwrap();
+        assert_eq!(rx.try_iter().count(), 1);
+    }
+}
@@ -1,12 +1,44 @@
 use crossbeam_channel::Sender;
 use serde::{Deserialize, Serialize};
 
+use crate::pattern::{FieldMatch, match_field};
+
+pub mod assign;
+pub mod config;
+pub mod dispatch;
+pub mod handle;
+pub mod pattern;
 pub mod platform;
+pub mod recording;
+pub mod resolver;
+pub mod simulated;
+#[cfg(feature = "tokio")]
+pub mod stream;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod udev_rules;
+pub mod validate;
+pub mod watch;
+
+pub use assign::{Ambiguity, Assignment, RejectReason, Rejection, assign};
+pub use config::{ConfigFormat, PidMatch, RuleSet};
+pub use dispatch::{Dispatcher, EventFilter, Subscription};
+pub use handle::{MonitorError, MonitorHandle, MonitorHealth, StopSignal};
+pub use recording::{RecordedEvent, RecordingMonitor, ReplayMonitor};
+pub use resolver::{Resolver, RoleEvent};
+pub use simulated::{Scenario, SimulatedMonitor, Step};
+#[cfg(feature = "tokio")]
+pub use stream::DeviceStream;
+pub use udev_rules::{UDEV_RULES_FILE, UdevOptions, UdevRules, UdevWarning, generate_udev_rules};
+pub use validate::{ConfigDiagnostic, DiagnosticKind, Severity, validate_rules};
+pub use watch::RulesWatcher;
 
 #[cfg(target_os = "linux")]
 pub use platform::linux::LinuxMonitor as Monitor;
 #[cfg(target_os = "macos")]
 pub use platform::macos::MacMonitor as Monitor;
+#[cfg(target_os = "linux")]
+pub use platform::sysfs::SysfsEnumerator;
 #[cfg(target_os = "windows")]
 pub use platform::windows::WindowsMonitor as Monitor;
 
@@ -14,14 +46,55 @@ pub use platform::windows::WindowsMonitor as Monitor;
 pub type RoleId = String;
 
 /// 原始设备信息（底层 OS 扫描到的数据）
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct RawDeviceInfo {
+    // Events and recordings carry plain integers; hex strings are accepted when reading
+    // (e.g. in scenario files)
+    // 事件与录制文件中为普通整数; 读取时也接受十六进制字符串 (例如场景文件中)
+    #[serde(deserialize_with = "config::usb_id::deserialize")]
     pub vid: u16,
+    #[serde(deserialize_with = "config::usb_id::deserialize")]
     pub pid: u16,
     pub serial: Option<String>,
     pub port_path: String,               // 平台特定的原生路径字符串
     pub system_path: String,             // 主路径 (macOS 下优先存 /dev/cu.*)
     pub system_path_alt: Option<String>, // 新增：备用路径 (macOS 下存 /dev/tty.*)
+    pub manufacturer: Option<String>,    // 厂商字符串 (iManufacturer)
+    pub product: Option<String>,         // 产品字符串 (iProduct)
+    #[serde(deserialize_with = "config::opt_usb_id::deserialize")]
+    pub bcd_device: Option<u16>, // 设备版本号 (bcdDevice)
+    #[serde(deserialize_with = "config::opt_class::deserialize")]
+    pub device_class: Option<u8>, // 设备类 (bDeviceClass)
+    pub interface_classes: Vec<u8>,      // 所有接口的类 (bInterfaceClass), 去重
+    pub drivers: Vec<String>, // 绑定在各接口上的驱动名 (如 ch341、cdc_acm、uvcvideo), 去重
+    pub children: Vec<ChildNode>, // 设备下所有带设备节点的子设备 (tty、video4linux、hidraw ...)
+}
+
+/// 设备下的一个设备节点
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChildNode {
+    pub subsystem: String, // 子系统 (tty / video4linux / hidraw / scsi_generic ...)
+    pub devnode: String,   // 设备节点 (/dev/ttyACM0)
+    pub kind: NodeKind,    // 节点的用途 (串口 / 视频采集 / 视频元数据 ...)
+    pub interface_number: Option<u8>, // 所属接口的编号 (bInterfaceNumber)
+    pub links: Vec<String>, // 指向该节点的符号链接 (/dev/serial/by-id/... 等)
+}
+
+/// 设备节点的用途
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    /// 串口 (tty)
+    Serial,
+    /// 视频采集节点 (video4linux, 可以读取画面)
+    Capture,
+    /// 视频元数据节点 (video4linux, UVC 摄像头的第二个 /dev/videoN, 无法读取画面)
+    Metadata,
+    /// 其它节点 (hidraw、sg ...)
+    #[default]
+    Other,
 }
 
 /// 匹配成功的设备
@@ -30,78 +103,362 @@ pub struct ResolvedDevice {
     pub role: RoleId,
     pub device: RawDeviceInfo,
     pub match_method: MatchMethod,
+    pub matched_pattern: Option<String>, // 若通过通配符模式匹配, 记录命中的模式
+    pub node: Option<ChildNode>,         // 规则通过 `node` 选中的设备节点
+    pub stable_path: Option<String>, // `path()` 对应节点的稳定符号链接 (by-id / by-path), 重启后不变
 }
 
-#[derive(Debug, Clone, Copy)]
+impl ResolvedDevice {
+    /// 应用程序应打开的路径: 选中的节点 > 备用路径 (tty) > 主路径
+    pub fn path(&self) -> &str {
+        self.node
+            .as_ref()
+            .map(|n| n.devnode.as_str())
+            .or(self.device.system_path_alt.as_deref())
+            .unwrap_or(&self.device.system_path)
+    }
+
+    /// 优先使用稳定路径, 没有时退回 `path()`
+    pub fn stable_or_path(&self) -> &str {
+        self.stable_path.as_deref().unwrap_or_else(|| self.path())
+    }
+}
+
+impl ChildNode {
+    /// 指定类型的符号链接
+    pub fn link(&self, kind: LinkPreference) -> Option<&str> {
+        let marker = match kind {
+            LinkPreference::ById => "/by-id/",
+            LinkPreference::ByPath => "/by-path/",
+        };
+        self.links
+            .iter()
+            .map(String::as_str)
+            .find(|link| link.contains(marker))
+    }
+
+    /// 稳定路径: 优先 `prefer` 类型的链接, 其次另一种
+    pub fn stable_path(&self, prefer: LinkPreference) -> Option<&str> {
+        let other = match prefer {
+            LinkPreference::ById => LinkPreference::ByPath,
+            LinkPreference::ByPath => LinkPreference::ById,
+        };
+        self.link(prefer).or_else(|| self.link(other))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MatchMethod {
+    /// 序列号完全一致
     SerialExact,
+    /// 序列号命中通配符模式 (如 "5AB01*")
+    SerialPattern,
+    /// 规则同时配置了 serial 和 port_path, 序列号缺失或不一致, 靠物理路径兜底
     TopologyFallback,
+    /// 规则只配置了 port_path, 物理路径一致
     PortPath,
+    /// 规则只配置了 port_path, 物理路径命中通配符模式 (如 "pci-0000:80:14.0-usb-0:6.*")
+    PortPathPattern,
+    /// 规则只配置了 VID/PID
     VidPidOnly,
 }
 
+/// 一次规则匹配的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleMatch {
+    pub method: MatchMethod,
+    /// 命中的通配符模式 (字面值精确匹配时为 None)
+    pub pattern: Option<String>,
+}
+
+impl RuleMatch {
+    fn new(method: MatchMethod) -> Self {
+        Self {
+            method,
+            pattern: None,
+        }
+    }
+
+    // Pick the exact or the pattern flavour of a method depending on how the field matched
+    // 根据字段的匹配方式, 选择精确或模式版本的匹配方法
+    fn from_field(hit: FieldMatch, exact: MatchMethod, pattern: MatchMethod, rule: &str) -> Self {
+        match hit {
+            FieldMatch::Exact => Self::new(exact),
+            FieldMatch::Pattern => Self {
+                method: pattern,
+                pattern: Some(rule.to_string()),
+            },
+        }
+    }
+}
+
+impl MatchMethod {
+    /// 匹配强度, 数值越大越可信 (序列号 > 物理路径 > 拓扑兜底 > 仅 VID/PID)
+    // TopologyFallback ranks below both port matches on purpose: the rule's serial, its
+    // primary identity, is missing or contradicted, so it only holds while no rule is fully
+    // satisfied by the device. A port-only rule (even a pattern) matched everything it asked for.
+    // TopologyFallback 有意排在两种物理路径匹配之后: 规则的主要身份 (序列号) 缺失或不一致,
+    // 因此只有在没有其它规则被该设备完全满足时才成立。只配置 port_path 的规则 (即使是模式)
+    // 已经满足了它要求的全部条件。
+    pub fn strength(self) -> u8 {
+        match self {
+            MatchMethod::SerialExact => 6,
+            MatchMethod::SerialPattern => 5,
+            MatchMethod::PortPath => 4,
+            MatchMethod::PortPathPattern => 3,
+            MatchMethod::TopologyFallback => 2,
+            MatchMethod::VidPidOnly => 1,
+        }
+    }
+}
+
 /// 系统事件
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum DeviceEvent {
     /// 一个符合配置要求的设备已上线
     Attached(RawDeviceInfo),
     /// 已知的设备已移除
     Detached(String),
+    /// 已上线设备的信息发生了变化 (例如 tty 节点在 Attached 之后才出现), system_path 不变
+    Updated(RawDeviceInfo),
+    /// 监听器出现故障 (连接中断时会自动重连, 期间不会收到热插拔事件)
+    MonitorError(MonitorError),
+    /// 连接已恢复; 断开期间漏掉的插拔已通过 Attached/Detached 补发
+    MonitorRecovered,
 }
 
 /// 单个设备的配置规则
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct DeviceRule {
     pub role: RoleId,
-    pub vid: u16,
-    pub pid: u16,
-    pub serial: Option<String>,    // 如果有 Serial，优先匹配
-    pub port_path: Option<String>, // 原生路径，用于回退
+    #[serde(with = "config::usb_id")]
+    pub vid: u16, // 十进制整数或十六进制字符串 ("0x1a86" / "1a86")
+    pub pid: PidMatch,          // 单个 PID、列表 [a, b] 或区间 {"min": a, "max": b}
+    pub serial: Option<String>, // 如果有 Serial，优先匹配 (支持 "5AB01*" 这样的通配符)
+    pub port_path: Option<String>, // 原生路径，用于回退 (同样支持通配符)
+    // Extra descriptor filters, all optional. Every filter that is set must hold.
+    // 额外的描述符过滤条件, 全部可选。配置了的条件必须全部满足。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>, // 厂商字符串 (支持通配符)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>, // 产品字符串 (支持通配符)
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "config::opt_usb_id"
+    )]
+    pub bcd_device: Option<u16>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "config::opt_class"
+    )]
+    pub device_class: Option<u8>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "config::opt_class"
+    )]
+    pub interface_class: Option<u8>, // 设备的任意一个接口属于该类即可
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub driver: Option<String>, // 设备的任意一个接口驱动匹配即可 (支持通配符)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<NodeSelector>, // 角色解析到设备下的哪个节点 (同时也是过滤条件: 该节点必须存在)
+    #[serde(default)]
+    pub serial_fallback: SerialFallback, // 序列号不一致时能否回退到物理路径
+    #[serde(default)]
+    pub on_ambiguous: AmbiguityPolicy, // 多个设备同时满足该规则时的处理策略
+    #[serde(default)]
+    pub prefer_link: LinkPreference, // 稳定路径优先使用哪种符号链接
+}
+
+/// 拓扑回退策略: 规则同时配置了 serial 和 port_path, 但设备序列号对不上时, 是否允许用物理路径兜底
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialFallback {
+    /// 不允许回退: 序列号必须一致
+    Never,
+    /// 仅当设备没有上报序列号时允许回退 (部分廉价芯片读不出 SN)
+    OnMissing,
+    /// 序列号缺失或不一致都允许回退 (兼容旧行为)
+    #[default]
+    Always,
+}
+
+/// 歧义处理策略: 多个设备以相同的匹配强度满足同一条规则时怎么办
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AmbiguityPolicy {
+    /// 按确定性的平局规则照常绑定, 同时报告歧义
+    #[default]
+    Bind,
+    /// 拒绝绑定, 直到歧义消除 (例如只剩一个候选设备)
+    Refuse,
+}
+
+/// 稳定路径的偏好
+///
+/// by-id 由设备自身 (厂商、产品、序列号) 决定, 换个 USB 口依然有效; 但两个没有序列号的相同设备
+/// 会争抢同一个 by-id 链接, 这时应使用由物理端口决定的 by-path。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkPreference {
+    /// /dev/serial/by-id/...、/dev/v4l/by-id/...
+    #[default]
+    ById,
+    /// /dev/serial/by-path/...、/dev/v4l/by-path/...
+    ByPath,
+}
+
+impl RawDeviceInfo {
+    /// 备用路径 (`system_path_alt`) 对应的子节点
+    pub fn alt_node(&self) -> Option<&ChildNode> {
+        let alt = self.system_path_alt.as_deref()?;
+        self.children.iter().find(|c| c.devnode == alt)
+    }
+}
+
+/// 设备节点选择器: 在 `RawDeviceInfo::children` 中选出一个节点
+/// 例如 `{"subsystem": "tty", "interface": 2}` 表示 "接口 2 的 tty"
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeSelector {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subsystem: Option<String>, // 子系统 (支持通配符)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<NodeKind>, // 节点的用途 (例如 "capture" 表示视频采集节点)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interface: Option<u8>, // 所属接口的编号
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>, // 满足条件的第几个节点 (从 0 开始, 默认第一个)
+}
+
+impl NodeSelector {
+    /// 选出满足条件的节点
+    pub fn select<'a>(&self, children: &'a [ChildNode]) -> Option<&'a ChildNode> {
+        children
+            .iter()
+            .filter(|child| {
+                self.subsystem
+                    .as_deref()
+                    .is_none_or(|rule| match_field(rule, &child.subsystem).is_some())
+                    && self.kind.is_none_or(|kind| child.kind == kind)
+                    && self
+                        .interface
+                        .is_none_or(|number| child.interface_number == Some(number))
+            })
+            .nth(self.index.unwrap_or(0))
+    }
 }
 
 impl DeviceRule {
     /// 核心匹配算法：严格模式
-    pub fn matches(&self, device: &RawDeviceInfo) -> Option<MatchMethod> {
+    pub fn matches(&self, device: &RawDeviceInfo) -> Option<RuleMatch> {
         // 1. 基础门槛：VID 和 PID 必须匹配 (Strict Mode)
-        if self.vid != device.vid || self.pid != device.pid {
+        if self.vid != device.vid || !self.pid.contains(device.pid) {
             return None;
         }
 
-        // 2. 策略 A: 序列号匹配 (优先级最高)
-        if let (Some(rule_sn), Some(dev_sn)) = (&self.serial, &device.serial)
-            && rule_sn == dev_sn
-        {
-            return Some(MatchMethod::SerialExact);
+        // 额外的描述符过滤条件同样是门槛, 不影响匹配强度
+        if !self.descriptors_match(device) {
+            return None;
         }
 
-        // 2. 其次匹配物理路径
-        if let Some(rule_path) = &self.port_path
-            && rule_path == &device.port_path
-        {
-            return Some(MatchMethod::PortPath);
-        }
+        let path_hit = self
+            .port_path
+            .as_deref()
+            .and_then(|rule_path| Some((rule_path, match_field(rule_path, &device.port_path)?)));
 
-        // 3. 如果规则里没写 SN 也没写 Path，则只要 VID/PID 对了就算匹配 (Loose 模式)
-        if self.serial.is_none() && self.port_path.is_none() {
-            return Some(MatchMethod::VidPidOnly);
-        }
+        match &self.serial {
+            Some(rule_sn) => {
+                // 2. 策略 A: 序列号匹配 (优先级最高)
+                if let Some(hit) = device
+                    .serial
+                    .as_deref()
+                    .and_then(|dev_sn| match_field(rule_sn, dev_sn))
+                {
+                    return Some(RuleMatch::from_field(
+                        hit,
+                        MatchMethod::SerialExact,
+                        MatchMethod::SerialPattern,
+                        rule_sn,
+                    ));
+                }
+
+                // 3. 策略 B: 拓扑路径匹配 (回退策略)
+                // 规则同时写了 SN 和 Path, 但设备的 SN 缺失或不一致, 此时由 serial_fallback 决定能否用路径兜底
+                let (rule_path, hit) = path_hit?;
+                let allowed = match self.serial_fallback {
+                    SerialFallback::Never => false,
+                    SerialFallback::OnMissing => device.serial.is_none(),
+                    SerialFallback::Always => true,
+                };
+                allowed.then(|| {
+                    RuleMatch::from_field(
+                        hit,
+                        MatchMethod::TopologyFallback,
+                        MatchMethod::TopologyFallback,
+                        rule_path,
+                    )
+                })
+            }
+            None => {
+                // 4. 规则只写了 Path: 物理路径匹配
+                if self.port_path.is_some() {
+                    return path_hit.map(|(rule_path, hit)| {
+                        RuleMatch::from_field(
+                            hit,
+                            MatchMethod::PortPath,
+                            MatchMethod::PortPathPattern,
+                            rule_path,
+                        )
+                    });
+                }
 
-        // 3. 策略 B: 拓扑路径匹配 (回退策略)
-        // 只有当序列号没匹配上（或配置没写序列号），且配置了路径时才尝试
-        if let Some(cfg_path) = &self.port_path
-            && cfg_path == &device.port_path
-        {
-            return Some(MatchMethod::TopologyFallback);
+                // 5. 如果规则里没写 SN 也没写 Path，则只要 VID/PID 对了就算匹配 (Loose 模式)
+                Some(RuleMatch::new(MatchMethod::VidPidOnly))
+            }
         }
+    }
 
-        None
+    // Check the optional descriptor filters (manufacturer, product, class, driver ...)
+    // 检查可选的描述符过滤条件 (厂商、产品、类、驱动 ...)
+    fn descriptors_match(&self, device: &RawDeviceInfo) -> bool {
+        let text = |rule: &Option<String>, dev: &Option<String>| match (rule, dev) {
+            (None, _) => true,
+            (Some(rule), Some(dev)) => match_field(rule, dev).is_some(),
+            (Some(_), None) => false,
+        };
+        let number = |rule: Option<u16>, dev: Option<u16>| rule.is_none() || rule == dev;
+
+        text(&self.manufacturer, &device.manufacturer)
+            && text(&self.product, &device.product)
+            && number(self.bcd_device, device.bcd_device)
+            && number(
+                self.device_class.map(u16::from),
+                device.device_class.map(u16::from),
+            )
+            && self
+                .interface_class
+                .is_none_or(|class| device.interface_classes.contains(&class))
+            && self.driver.as_deref().is_none_or(|rule| {
+                device
+                    .drivers
+                    .iter()
+                    .any(|dev| match_field(rule, dev).is_some())
+            })
+            && self
+                .node
+                .as_ref()
+                .is_none_or(|node| node.select(&device.children).is_some())
     }
 }
 
 /// 统一的监听器 trait
 pub trait DeviceMonitor {
-    /// 启动监听，阻塞当前线程或在后台运行，通过 channel 发送事件
-    fn start(&self, tx: Sender<DeviceEvent>) -> anyhow::Result<()>;
+    /// 启动后台监听，通过 channel 发送事件
+    /// 返回的句柄被 drop 时监听线程随之停止，调用方需要在监听期间持有它
+    fn start(&self, tx: Sender<DeviceEvent>) -> anyhow::Result<MonitorHandle>;
 
     /// 立即扫描一次当前所有设备（用于程序启动时的初始状态构建）
     fn scan_now(&self) -> anyhow::Result<Vec<RawDeviceInfo>>;
@@ -121,3 +478,301 @@ pub fn get_monitor() -> Box<dyn DeviceMonitor> {
     #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
     panic!("Unsupported OS");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fully described CH343 with two serial interfaces
+    // 描述符完整、带两个串口接口的 CH343
+    fn device(serial: Option<&str>, port_path: &str) -> RawDeviceInfo {
+        RawDeviceInfo {
+            vid: 0x1a86,
+            pid: 0x55d3,
+            serial: serial.map(str::to_string),
+            port_path: port_path.to_string(),
+            system_path: "/sys/devices/pci0000:00/0000:00:14.0/usb3/3-6/3-6.3".to_string(),
+            system_path_alt: Some("/dev/ttyACM0".to_string()),
+            manufacturer: Some("QinHeng Electronics".to_string()),
+            product: Some("USB Single Serial".to_string()),
+            bcd_device: Some(0x0445),
+            device_class: Some(0x02),
+            interface_classes: vec![0x02, 0x0a],
+            drivers: vec!["cdc_acm".to_string()],
+            children: vec![
+                ChildNode {
+                    subsystem: "tty".to_string(),
+                    devnode: "/dev/ttyACM0".to_string(),
+                    kind: NodeKind::Serial,
+                    interface_number: Some(0),
+                    links: vec![],
+                },
+                ChildNode {
+                    subsystem: "tty".to_string(),
+                    devnode: "/dev/ttyACM1".to_string(),
+                    kind: NodeKind::Serial,
+                    interface_number: Some(2),
+                    links: vec![
+                        "/dev/serial/by-id/usb-QinHeng_Electronics_USB_Single_Serial-if02"
+                            .to_string(),
+                        "/dev/serial/by-path/pci-0000:80:14.0-usb-0:6.3:1.2".to_string(),
+                    ],
+                },
+            ],
+        }
+    }
+
+    fn rule(serial: Option<&str>, port_path: Option<&str>) -> DeviceRule {
+        test_support::rule_at("main_arm", serial, port_path)
+    }
+
+    fn method(rule: &DeviceRule, device: &RawDeviceInfo) -> Option<MatchMethod> {
+        rule.matches(device).map(|m| m.method)
+    }
+
+    const PORT: &str = "pci-0000:80:14.0-usb-0:6.3";
+    const OTHER_PORT: &str = "pci-0000:80:14.0-usb-0:6.4";
+
+    #[test]
+    fn vid_pid_mismatch_never_matches() {
+        let mut dev = device(Some("5AB0183575"), PORT);
+        dev.pid = 0x7523;
+        assert_eq!(method(&rule(None, None), &dev), None);
+        assert_eq!(method(&rule(Some("5AB0183575"), Some(PORT)), &dev), None);
+    }
+
+    #[test]
+    fn serial_exact() {
+        let dev = device(Some("5AB0183575"), OTHER_PORT);
+        assert_eq!(
+            method(&rule(Some("5AB0183575"), None), &dev),
+            Some(MatchMethod::SerialExact)
+        );
+        // Serial wins even when the configured port differs
+        assert_eq!(
+            method(&rule(Some("5AB0183575"), Some(PORT)), &dev),
+            Some(MatchMethod::SerialExact)
+        );
+    }
+
+    #[test]
+    fn serial_only_rule_rejects_other_serial() {
+        assert_eq!(
+            method(
+                &rule(Some("5AB0183575"), None),
+                &device(Some("5AB0179252"), PORT)
+            ),
+            None
+        );
+        assert_eq!(
+            method(&rule(Some("5AB0183575"), None), &device(None, PORT)),
+            None
+        );
+    }
+
+    #[test]
+    fn path_only_rule_is_port_path() {
+        let r = rule(None, Some(PORT));
+        assert_eq!(
+            method(&r, &device(Some("5AB0183575"), PORT)),
+            Some(MatchMethod::PortPath)
+        );
+        assert_eq!(method(&r, &device(None, PORT)), Some(MatchMethod::PortPath));
+        assert_eq!(method(&r, &device(None, OTHER_PORT)), None);
+    }
+
+    #[test]
+    fn loose_rule_is_vid_pid_only() {
+        assert_eq!(
+            method(&rule(None, None), &device(None, PORT)),
+            Some(MatchMethod::VidPidOnly)
+        );
+    }
+
+    #[test]
+    fn topology_fallback_on_missing_or_different_serial() {
+        let r = rule(Some("5AB0183575"), Some(PORT));
+        assert_eq!(
+            method(&r, &device(None, PORT)),
+            Some(MatchMethod::TopologyFallback)
+        );
+        assert_eq!(
+            method(&r, &device(Some("5AB0179252"), PORT)),
+            Some(MatchMethod::TopologyFallback)
+        );
+        assert_eq!(method(&r, &device(None, OTHER_PORT)), None);
+        assert_eq!(method(&r, &device(Some("5AB0179252"), OTHER_PORT)), None);
+    }
+
+    #[test]
+    fn topology_fallback_policy() {
+        let mut r = rule(Some("5AB0183575"), Some(PORT));
+
+        r.serial_fallback = SerialFallback::OnMissing;
+        assert_eq!(
+            method(&r, &device(None, PORT)),
+            Some(MatchMethod::TopologyFallback)
+        );
+        assert_eq!(method(&r, &device(Some("5AB0179252"), PORT)), None);
+
+        r.serial_fallback = SerialFallback::Never;
+        assert_eq!(method(&r, &device(None, PORT)), None);
+        assert_eq!(method(&r, &device(Some("5AB0179252"), PORT)), None);
+        assert_eq!(
+            method(&r, &device(Some("5AB0183575"), PORT)),
+            Some(MatchMethod::SerialExact)
+        );
+    }
+
+    #[test]
+    fn serial_fallback_defaults_to_always() {
+        let r: DeviceRule = serde_json::from_str(
+            r#"{"role": "main_arm", "vid": 6790, "pid": 21971, "serial": "5AB0183575", "port_path": null}"#,
+        )
+        .unwrap();
+        assert_eq!(r.serial_fallback, SerialFallback::Always);
+
+        let r: DeviceRule = serde_json::from_str(
+            r#"{"role": "main_arm", "vid": 6790, "pid": 21971, "serial": null, "port_path": null, "serial_fallback": "on_missing"}"#,
+        )
+        .unwrap();
+        assert_eq!(r.serial_fallback, SerialFallback::OnMissing);
+    }
+
+    #[test]
+    fn serial_pattern() {
+        let r = rule(Some("5AB01*"), None);
+        let m = r.matches(&device(Some("5AB0183575"), PORT)).unwrap();
+        assert_eq!(m.method, MatchMethod::SerialPattern);
+        assert_eq!(m.pattern.as_deref(), Some("5AB01*"));
+        assert_eq!(method(&r, &device(Some("5AB0279252"), PORT)), None);
+        assert_eq!(method(&r, &device(None, PORT)), None);
+    }
+
+    #[test]
+    fn port_path_pattern() {
+        let r = rule(None, Some("pci-0000:80:14.0-usb-0:6.*"));
+        let m = r.matches(&device(None, PORT)).unwrap();
+        assert_eq!(m.method, MatchMethod::PortPathPattern);
+        assert_eq!(m.pattern.as_deref(), Some("pci-0000:80:14.0-usb-0:6.*"));
+        assert_eq!(
+            method(&r, &device(None, "pci-0000:80:14.0-usb-0:7.1")),
+            None
+        );
+
+        // A pattern used as the topology fallback still reports the pattern
+        let r = rule(Some("5AB0183575"), Some("pci-0000:80:14.0-usb-0:6.*"));
+        let m = r.matches(&device(None, PORT)).unwrap();
+        assert_eq!(m.method, MatchMethod::TopologyFallback);
+        assert_eq!(m.pattern.as_deref(), Some("pci-0000:80:14.0-usb-0:6.*"));
+    }
+
+    #[test]
+    fn pid_list_gate() {
+        let mut r = rule(None, None);
+        r.pid = PidMatch::List(vec![0x7523, 0x55d3]);
+        assert_eq!(
+            method(&r, &device(None, PORT)),
+            Some(MatchMethod::VidPidOnly)
+        );
+        r.pid = PidMatch::Range {
+            min: 0x7520,
+            max: 0x752f,
+        };
+        assert_eq!(method(&r, &device(None, PORT)), None);
+    }
+
+    #[test]
+    fn descriptor_filters() {
+        let dev = device(None, PORT);
+        let mut r = rule(None, None);
+        r.product = Some("USB Single*".to_string());
+        r.interface_class = Some(0x0a);
+        r.driver = Some("cdc_acm".to_string());
+        r.bcd_device = Some(0x0445);
+        assert_eq!(method(&r, &dev), Some(MatchMethod::VidPidOnly));
+
+        r.driver = Some("ch341".to_string());
+        assert_eq!(method(&r, &dev), None);
+
+        let mut r = rule(None, None);
+        r.manufacturer = Some("QinHeng Electronics".to_string());
+        let mut anonymous = device(None, PORT);
+        anonymous.manufacturer = None;
+        assert_eq!(method(&r, &anonymous), None);
+
+        let r: DeviceRule = serde_json::from_str(
+            r#"{"role": "cam", "vid": "046d", "pid": "082d", "bcd_device": "0011", "interface_class": "0x0e"}"#,
+        )
+        .unwrap();
+        assert_eq!(r.bcd_device, Some(0x0011));
+        assert_eq!(r.interface_class, Some(0x0e));
+    }
+
+    #[test]
+    fn node_selector_picks_a_child() {
+        let dev = device(None, PORT);
+        let mut r: DeviceRule = serde_json::from_str(
+            r#"{"role": "debug", "vid": "1a86", "pid": "55d3", "node": {"subsystem": "tty", "interface": 2}}"#,
+        )
+        .unwrap();
+        assert_eq!(method(&r, &dev), Some(MatchMethod::VidPidOnly));
+
+        let resolved = assign(std::slice::from_ref(&r), std::slice::from_ref(&dev));
+        assert_eq!(resolved.bound[0].path(), "/dev/ttyACM1");
+        assert_eq!(
+            resolved.bound[0].stable_or_path(),
+            "/dev/serial/by-id/usb-QinHeng_Electronics_USB_Single_Serial-if02"
+        );
+
+        r.prefer_link = LinkPreference::ByPath;
+        let resolved = assign(std::slice::from_ref(&r), std::slice::from_ref(&dev));
+        assert_eq!(
+            resolved.bound[0].stable_path.as_deref(),
+            Some("/dev/serial/by-path/pci-0000:80:14.0-usb-0:6.3:1.2")
+        );
+
+        // Without links the plain node is used
+        // 没有符号链接时使用节点本身
+        let plain = assign(&[rule(None, None)], std::slice::from_ref(&dev));
+        assert_eq!(plain.bound[0].stable_path, None);
+        assert_eq!(plain.bound[0].stable_or_path(), "/dev/ttyACM0");
+
+        // The node is a filter as well: no such node, no match
+        // 节点同时也是过滤条件: 没有这样的节点就不匹配
+        r.node = Some(NodeSelector {
+            subsystem: Some("video4linux".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(method(&r, &dev), None);
+
+        let second = NodeSelector {
+            kind: Some(NodeKind::Serial),
+            index: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            second.select(&dev.children).map(|n| n.devnode.as_str()),
+            Some("/dev/ttyACM1")
+        );
+    }
+
+    #[test]
+    fn device_info_ids_are_plain_integers_on_the_wire() {
+        let dev = device(Some("5AB0183575"), PORT);
+        let value = serde_json::to_value(&dev).unwrap();
+        assert_eq!(value["vid"], 6790);
+        assert_eq!(value["pid"], 21971);
+        assert_eq!(value["bcd_device"], 0x0445);
+        assert_eq!(value["device_class"], 2);
+        assert_eq!(serde_json::from_value::<RawDeviceInfo>(value).unwrap(), dev);
+
+        let hex: RawDeviceInfo =
+            serde_json::from_str(r#"{"vid": "0x1a86", "pid": "55d3", "device_class": "0x02"}"#)
+                .unwrap();
+        assert_eq!(
+            (hex.vid, hex.pid, hex.device_class),
+            (0x1a86, 0x55d3, Some(2))
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 use crossterm::{
     event::{self, Event, KeyCode},
     execute,
@@ -6,8 +6,13 @@ use crossterm::{
 };
 use ratatui::{prelude::*, widgets::*};
 use std::fs;
-use std::{collections::HashMap, io, time::Duration};
-use usb_resolver::{DeviceEvent, DeviceRule, RawDeviceInfo, get_monitor};
+use std::path::Path;
+use std::{collections::HashMap, env, io, process, time::Duration};
+use usb_resolver::{
+    Assignment, ConfigDiagnostic, ConfigFormat, DeviceEvent, DeviceMonitor, DeviceRule,
+    LinkPreference, RawDeviceInfo, RecordingMonitor, ReplayMonitor, RuleSet, RulesWatcher,
+    UdevOptions, assign, generate_udev_rules, get_monitor,
+};
 
 // --- 状态管理 ---
 struct App {
@@ -23,8 +28,14 @@ struct App {
     // 配置文件里的规则
     rules: Vec<DeviceRule>,
 
+    // 当前设备与角色的全局分配结果
+    assignment: Assignment,
+
     // 弹窗状态：如果为 Some，则显示该设备的详情
     popup_device: Option<RawDeviceInfo>,
+
+    // 最近一次规则文件重载的结果, 显示在底栏
+    status: Option<String>,
 }
 
 impl App {
@@ -37,7 +48,9 @@ impl App {
             sorted_devices: Vec::new(),
             table_state: state,
             rules,
+            assignment: Assignment::default(),
             popup_device: None,
+            status: None,
         }
     }
 
@@ -46,16 +59,16 @@ impl App {
         let mut list: Vec<RawDeviceInfo> = self.devices_map.values().cloned().collect();
         // 按 system_path 排序，保证列表稳定性
         list.sort_by(|a, b| a.system_path.cmp(&b.system_path));
+        // 所有设备和规则一起参与分配, 保证一个角色只对应一个设备
+        self.assignment = assign(&self.rules, &list);
         self.sorted_devices = list;
     }
 
     fn match_role(&self, info: &RawDeviceInfo) -> String {
-        for rule in &self.rules {
-            if rule.matches(info).is_some() {
-                return rule.role.clone();
-            }
-        }
-        "-".to_string()
+        self.assignment
+            .role_of(&info.system_path)
+            .map(|b| b.role.clone())
+            .unwrap_or_else(|| "-".to_string())
     }
 
     // --- 导航逻辑 ---
@@ -100,18 +113,51 @@ impl App {
     }
 }
 
+// 默认配置文件, 按顺序查找第一个存在的
+const DEFAULT_CONFIGS: [&str; 4] = [
+    "device_config.json",
+    "device_config.toml",
+    "device_config.yaml",
+    "device_config.yml",
+];
+
+fn default_config() -> &'static str {
+    DEFAULT_CONFIGS
+        .into_iter()
+        .find(|path| Path::new(path).exists())
+        .unwrap_or(DEFAULT_CONFIGS[0])
+}
+
 fn main() -> Result<()> {
-    // 加载配置
-    let rules = if let Ok(content) = fs::read_to_string("device_config.json") {
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        Vec::new()
-    };
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    // 启动 Monitor
-    let monitor = get_monitor();
+    // 命令行模式: --check-config [path]
+    if args.first().map(String::as_str) == Some("--check-config") {
+        let path = match args.get(1) {
+            Some(path) => path.as_str(),
+            None => default_config(),
+        };
+        process::exit(check_config(path));
+    }
+
+    // 命令行模式: --udev-rules [path] [--mode MODE] [--group GROUP] [--output FILE]
+    if args.first().map(String::as_str) == Some("--udev-rules") {
+        process::exit(udev_rules(&args[1..]));
+    }
+
+    // 加载配置, 并在文件变化时热重载
+    let config_path = default_config();
+    let rules = load_rules(config_path)?;
+    let (rules_tx, rules_rx) = crossbeam_channel::unbounded();
+    let _watcher = RulesWatcher::new(config_path, move |result| {
+        let _ = rules_tx.send(result);
+    })?;
+
+    // 启动 Monitor (--record FILE 录制事件, --replay FILE [--speed N] 回放录制的事件)
+    let monitor = monitor_from_args(&args)?;
     let (tx, rx) = crossbeam_channel::unbounded();
-    monitor.start(tx)?;
+    // 句柄需要一直持有, 被 drop 时监听线程会停止
+    let _monitor_handle = monitor.start(tx)?;
 
     // TUI Setup
     enable_raw_mode()?;
@@ -123,7 +169,7 @@ fn main() -> Result<()> {
     let mut app = App::new(rules);
 
     // Run Loop
-    let res = run_app(&mut terminal, &mut app, &rx);
+    let res = run_app(&mut terminal, &mut app, &rx, &rules_rx);
 
     // Cleanup
     disable_raw_mode()?;
@@ -137,10 +183,177 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+// 根据命令行参数选择监听器: 当前平台, 录制, 或回放
+fn monitor_from_args(args: &[String]) -> Result<Box<dyn DeviceMonitor>> {
+    let mut record = None;
+    let mut replay = None;
+    let mut speed = 1.0;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(value) = args.next() else {
+            bail!("unexpected argument: {}", arg);
+        };
+        match arg.as_str() {
+            "--record" => record = Some(value.clone()),
+            "--replay" => replay = Some(value.clone()),
+            "--speed" => match value.parse::<f64>() {
+                Ok(value) => speed = value,
+                Err(_) => bail!("--speed needs a number, got {}", value),
+            },
+            _ => bail!("unexpected argument: {}", arg),
+        }
+    }
+
+    let monitor: Box<dyn DeviceMonitor> = match replay {
+        Some(path) => Box::new(
+            ReplayMonitor::load(path)?
+                .with_speed(speed)
+                .context("--speed")?,
+        ),
+        None => get_monitor(),
+    };
+    Ok(match record {
+        Some(path) => Box::new(RecordingMonitor::new(monitor, path)),
+        None => monitor,
+    })
+}
+
+// 加载配置: 文件不存在时视为没有规则, 但解析失败或校验出错时直接报错退出
+fn load_rules(path: &str) -> Result<Vec<DeviceRule>> {
+    if !Path::new(path).exists() {
+        return Ok(Vec::new());
+    }
+
+    let rule_set = RuleSet::load(path)?;
+
+    let errors: Vec<String> = rule_set
+        .validate()
+        .into_iter()
+        .filter(|d| d.is_error())
+        .map(|d| d.to_string())
+        .collect();
+    if !errors.is_empty() {
+        bail!(
+            "{} is invalid (run with --check-config for details):\n{}",
+            path,
+            errors.join("\n")
+        );
+    }
+
+    Ok(rule_set.rules)
+}
+
+// --check-config: 打印所有诊断, 返回进程退出码
+fn check_config(path: &str) -> i32 {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return 2;
+        }
+    };
+
+    let format = ConfigFormat::from_path(path).unwrap_or_else(|| ConfigFormat::detect(&content));
+    let rule_set = match RuleSet::parse(&content, format) {
+        Ok(rule_set) => rule_set,
+        Err(diagnostic) => {
+            eprintln!("{} ({:?}): {}", path, format, diagnostic);
+            return 1;
+        }
+    };
+
+    let diagnostics = rule_set.validate();
+    for diagnostic in &diagnostics {
+        eprintln!("{}: {}", path, diagnostic);
+    }
+
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+    println!(
+        "{}: {} rule(s), {} error(s), {} warning(s)",
+        path,
+        rule_set.rules.len(),
+        errors,
+        warnings
+    );
+
+    if errors > 0 { 1 } else { 0 }
+}
+
+// --udev-rules: 把规则转换为 udev 规则, 警告打印到 stderr, 返回进程退出码
+fn udev_rules(args: &[String]) -> i32 {
+    let mut path = None;
+    let mut output = None;
+    let mut options = UdevOptions::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let target = match arg.as_str() {
+            "--mode" => &mut options.mode,
+            "--group" => &mut options.group,
+            "--output" | "-o" => &mut output,
+            _ if path.is_none() && !arg.starts_with('-') => {
+                path = Some(arg.clone());
+                continue;
+            }
+            _ => {
+                eprintln!("unexpected argument: {}", arg);
+                return 2;
+            }
+        };
+        match args.next() {
+            Some(value) => *target = Some(value.clone()),
+            None => {
+                eprintln!("{} needs a value", arg);
+                return 2;
+            }
+        }
+    }
+
+    let path = path.unwrap_or_else(|| default_config().to_string());
+    let rules = match load_rules(&path) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("{}: {:#}", path, e);
+            return 1;
+        }
+    };
+
+    let generated = match generate_udev_rules(&rules, &options) {
+        Ok(generated) => generated,
+        Err(e) => {
+            eprintln!("{}: {:#}", path, e);
+            return 1;
+        }
+    };
+    for warning in &generated.warnings {
+        eprintln!("{}: {}", path, warning);
+    }
+
+    match output {
+        Some(output) => {
+            if let Err(e) = fs::write(&output, &generated.content) {
+                eprintln!("{}: {}", output, e);
+                return 2;
+            }
+            println!(
+                "{}: {} rule(s) written, {} warning(s)",
+                output,
+                rules.len(),
+                generated.warnings.len()
+            );
+        }
+        None => print!("{}", generated.content),
+    }
+    0
+}
+
 fn run_app<B: Backend>(
     terminal: &mut Terminal<B>,
     app: &mut App,
     rx: &crossbeam_channel::Receiver<DeviceEvent>,
+    rules_rx: &crossbeam_channel::Receiver<Result<RuleSet, Vec<ConfigDiagnostic>>>,
 ) -> anyhow::Result<()> {
     loop {
         // --- Draw ---
@@ -152,7 +365,7 @@ fn run_app<B: Backend>(
         let mut need_refresh = false;
         while let Ok(event) = rx.try_recv() {
             match event {
-                DeviceEvent::Attached(dev) => {
+                DeviceEvent::Attached(dev) | DeviceEvent::Updated(dev) => {
                     app.devices_map.insert(dev.system_path.clone(), dev);
                     need_refresh = true;
                 }
@@ -160,6 +373,32 @@ fn run_app<B: Backend>(
                     app.devices_map.remove(&path);
                     need_refresh = true;
                 }
+                DeviceEvent::MonitorError(error) => {
+                    app.status = Some(format!("Monitor error: {}", error));
+                }
+                DeviceEvent::MonitorRecovered => {
+                    app.status = Some("Monitor reconnected".to_string());
+                }
+            }
+        }
+
+        // --- Handle Rules Reload ---
+        while let Ok(result) = rules_rx.try_recv() {
+            match result {
+                Ok(rule_set) => {
+                    app.status = Some(format!("Rules reloaded ({})", rule_set.rules.len()));
+                    app.rules = rule_set.rules;
+                    need_refresh = true;
+                }
+                Err(diagnostics) => {
+                    // 新文件有错误, 继续使用旧规则
+                    let first = diagnostics
+                        .iter()
+                        .find(|d| d.is_error())
+                        .map(|d| d.to_string())
+                        .unwrap_or_default();
+                    app.status = Some(format!("Rules NOT reloaded: {}", first));
+                }
             }
         }
         if need_refresh {
@@ -226,7 +465,10 @@ fn ui(f: &mut Frame, app: &mut App) {
         .iter()
         .map(|item| {
             let role = app.match_role(item);
-            let style = if role != "-" {
+            let style = if app.assignment.is_ambiguous(&role) {
+                // 歧义: 多个设备同等程度地满足该角色
+                Style::default().fg(Color::Yellow)
+            } else if role != "-" {
                 Style::default().fg(Color::Green)
             } else {
                 Style::default()
@@ -279,13 +521,13 @@ fn ui(f: &mut Frame, app: &mut App) {
     } else {
         "↑/↓: Select | Enter: Details | q: Quit"
     };
-    let footer = Paragraph::new(format!(
-        "Total: {} | {}",
-        app.sorted_devices.len(),
-        help_text
-    ))
-    .style(Style::default().fg(Color::Gray))
-    .block(Block::default().borders(Borders::ALL));
+    let mut footer_text = format!("Total: {} | {}", app.sorted_devices.len(), help_text);
+    if let Some(status) = &app.status {
+        footer_text = format!("{} | {}", footer_text, status);
+    }
+    let footer = Paragraph::new(footer_text)
+        .style(Style::default().fg(Color::Gray))
+        .block(Block::default().borders(Borders::ALL));
     f.render_widget(footer, chunks[2]);
 
     // Render Popup
@@ -304,7 +546,7 @@ fn render_popup(f: &mut Frame, dev: &RawDeviceInfo, app: &App) {
     let role = app.match_role(dev);
 
     // 准备详细信息文本
-    let text = vec![
+    let mut text = vec![
         Line::from(vec![
             Span::styled("Role: ", Style::default().fg(Color::Yellow)),
             Span::raw(role),
@@ -335,6 +577,18 @@ fn render_popup(f: &mut Frame, dev: &RawDeviceInfo, app: &App) {
             Span::styled("Port Path: ", Style::default().fg(Color::Magenta)),
             Span::raw(&dev.port_path),
         ]),
+        Line::from(vec![
+            Span::styled("Manufacturer: ", Style::default().fg(Color::Magenta)),
+            Span::raw(dev.manufacturer.clone().unwrap_or("N/A".to_string())),
+        ]),
+        Line::from(vec![
+            Span::styled("Product: ", Style::default().fg(Color::Magenta)),
+            Span::raw(dev.product.clone().unwrap_or("N/A".to_string())),
+        ]),
+        Line::from(vec![
+            Span::styled("Drivers: ", Style::default().fg(Color::Magenta)),
+            Span::raw(dev.drivers.join(", ")),
+        ]),
         Line::from(""),
         Line::from(Span::styled(
             "--- Paths ---",
@@ -350,6 +604,32 @@ fn render_popup(f: &mut Frame, dev: &RawDeviceInfo, app: &App) {
         ]),
     ];
 
+    // 子设备节点 (接口号 + 子系统 + 节点)
+    if !dev.children.is_empty() {
+        text.push(Line::from(""));
+        text.push(Line::from(Span::styled(
+            "--- Nodes ---",
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        for child in &dev.children {
+            let interface = child
+                .interface_number
+                .map(|n| format!("if{} ", n))
+                .unwrap_or_default();
+            text.push(Line::from(vec![
+                Span::styled(
+                    format!("{}{} ({:?}): ", interface, child.subsystem, child.kind),
+                    Style::default().fg(Color::Green),
+                ),
+                Span::raw(&child.devnode),
+            ]));
+            // 稳定路径 (by-id 优先)
+            if let Some(link) = child.stable_path(LinkPreference::ById) {
+                text.push(Line::from(vec![Span::raw("    -> "), Span::raw(link)]));
+            }
+        }
+    }
+
     let block = Block::default()
         .title(" Device Details (Press ESC to close) ")
         .borders(Borders::ALL)
@@ -0,0 +1,50 @@
+// Glob patterns for string fields of a rule (serial, port_path, ...)
+// 规则中字符串字段 (serial、port_path 等) 的通配符匹配
+//
+// A value containing any of `*`, `?` or `[` is treated as a glob pattern, everything else
+// is compared literally. Examples:
+//     "5AB01*"                        -> any serial starting with 5AB01
+//     "pci-0000:80:14.0-usb-0:6.*"    -> any port below hub port 6
+// 含有 `*`、`?` 或 `[` 的值按通配符模式处理, 其余按字面值精确比较。
+
+use glob::Pattern;
+
+const GLOB_CHARS: [char; 3] = ['*', '?', '['];
+
+/// 一次字段匹配的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldMatch {
+    /// 字面值完全一致
+    Exact,
+    /// 通过通配符模式匹配
+    Pattern,
+}
+
+/// 该值是否是通配符模式
+pub fn is_pattern(value: &str) -> bool {
+    value.contains(GLOB_CHARS)
+}
+
+/// 校验通配符模式的语法, 返回错误描述
+pub fn check_pattern(value: &str) -> Result<(), String> {
+    if !is_pattern(value) {
+        return Ok(());
+    }
+    Pattern::new(value)
+        .map(|_| ())
+        .map_err(|e| format!("invalid pattern '{}': {}", value, e))
+}
+
+/// 用规则中的值 (字面值或模式) 去匹配设备上的值
+pub fn match_field(rule_value: &str, device_value: &str) -> Option<FieldMatch> {
+    if !is_pattern(rule_value) {
+        return (rule_value == device_value).then_some(FieldMatch::Exact);
+    }
+
+    // An invalid pattern never matches
+    // 非法的模式永远不会匹配
+    Pattern::new(rule_value)
+        .ok()
+        .filter(|p| p.matches(device_value))
+        .map(|_| FieldMatch::Pattern)
+}
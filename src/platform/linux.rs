@@ -13,24 +13,65 @@
 // Debian/Ubuntu: sudo apt install libudev-dev
 // Fedora: sudo dnf install systemd-devel
 //
+// The listener thread sleeps in poll() on the udev socket and on a wake-up pipe, so hotplug
+// events are delivered immediately, an idle system is never woken up, and a stop request
+// interrupts the wait at once.
+// 监听线程在 udev socket 与唤醒管道上阻塞于 poll(), 因此热插拔事件会被立即送达,
+// 空闲时不会被唤醒, 停止请求也能立即打断等待。
 
-use std::{thread, time::Duration};
+#[cfg(feature = "tokio")]
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::Mutex,
+    time::Duration,
+};
 
 use anyhow::Result;
 use crossbeam_channel::Sender;
-use log::info;
-use udev::{Device, Enumerator, EventType};
+#[cfg(feature = "tokio")]
+use futures_core::Stream;
+use log::{info, trace, warn};
+#[cfg(feature = "tokio")]
+use tokio::io::{Interest, unix::AsyncFd};
+use udev::{Device, Enumerator, MonitorSocket};
 
-use crate::{DeviceEvent, DeviceMonitor, RawDeviceInfo};
+use super::sysfs::SysDevice;
+use crate::{
+    ChildNode, DeviceEvent, DeviceMonitor, MonitorError, MonitorHandle, NodeKind, RawDeviceInfo,
+    StopSignal,
+};
 
 const ID_VENDOR: &str = "idVendor";
 const ID_PRODUCT: &str = "idProduct";
 const USB_SERIAL: &str = "serial";
+const USB_MANUFACTURER: &str = "manufacturer";
+const USB_PRODUCT: &str = "product";
+const BCD_DEVICE: &str = "bcdDevice";
+const B_DEVICE_CLASS: &str = "bDeviceClass";
+const B_INTERFACE_CLASS: &str = "bInterfaceClass";
+const B_INTERFACE_NUMBER: &str = "bInterfaceNumber";
 
 const ID_VENDOR_ID: &str = "ID_VENDOR_ID";
 const ID_MODEL_ID: &str = "ID_MODEL_ID";
 const ID_SERIAL_SHORT: &str = "ID_SERIAL_SHORT";
 const ID_PATH: &str = "ID_PATH";
+const ID_VENDOR_PROP: &str = "ID_VENDOR";
+const ID_MODEL_PROP: &str = "ID_MODEL";
+const DEVLINKS: &str = "DEVLINKS";
+const ID_V4L_CAPABILITIES: &str = "ID_V4L_CAPABILITIES";
+const V4L_INDEX: &str = "index";
+
+// Subsystems of the child nodes a device gains after its own add event
+// 设备自身的 add 事件之后才出现的子节点所属的子系统
+const NODE_SUBSYSTEMS: [&str; 3] = ["tty", "video4linux", "hidraw"];
 
 pub struct LinuxMonitor;
 
@@ -45,100 +86,162 @@ impl LinuxMonitor {
         Self
     }
 
-    // Recursively search for the TTY node
-    // Given a USB device, find its corresponding /dev/ttyUSBx or /dev/ttyACMx
-    // 递归查找 TTY 节点
-    // 给定一个 USB 设备, 找到它对应的 /dev/ttyUSBx 或者 /dev/ttyACMx
-    fn find_tty_node(usb_dev: &Device) -> Option<String> {
-        // Create an enumerator to iterate through the sub-devices.
-        // 创建一个枚举器, 用于遍历子设备
-        let mut enumerator = Enumerator::new().ok()?;
+    // Descendants that belong to this usb_device, not to a device plugged into it (hubs).
+    // A downstream usb_device is skipped itself (its /dev/bus/usb node is not ours), and so
+    // is everything below it, whose nearest usb_device is the downstream one.
+    // 属于该 usb_device 的后代, 不包括插在它下面的设备 (集线器)。下游的 usb_device 本身被跳过
+    // (它的 /dev/bus/usb 节点不属于我们), 它下面的一切同样被跳过, 因为它们最近的 usb_device
+    // 是下游设备。
+    fn own_descendants<D: SysDevice>(usb_dev: &D) -> Vec<D> {
+        let syspath = usb_dev.syspath();
+        usb_dev
+            .descendants()
+            .into_iter()
+            .filter(|child| {
+                child.devtype().as_deref() != Some("usb_device")
+                    && child
+                        .parent_with("usb", "usb_device")
+                        .is_some_and(|parent| parent.syspath() == syspath)
+            })
+            .collect()
+    }
+
+    // Collect every descendant that has a device node (tty, video4linux, hidraw, sg ...)
+    // 收集所有带设备节点的子设备 (tty、video4linux、hidraw、sg ...)
+    fn find_children<D: SysDevice>(usb_dev: &D) -> Vec<ChildNode> {
+        let mut children: Vec<ChildNode> = Self::own_descendants(usb_dev)
+            .into_iter()
+            .filter_map(|child| {
+                let devnode = child.devnode()?;
+                let interface_number = child
+                    .parent_with("usb", "usb_interface")
+                    .and_then(|i| Self::hex_attribute(&i, B_INTERFACE_NUMBER))
+                    .and_then(|n| u8::try_from(n).ok());
+                let links = child
+                    .property(DEVLINKS)
+                    .map(|l| l.split_whitespace().map(str::to_string).collect())
+                    .unwrap_or_default();
+
+                let subsystem = child.subsystem()?;
+                let kind = node_kind(
+                    &subsystem,
+                    child.property(ID_V4L_CAPABILITIES).as_deref(),
+                    child.attribute(V4L_INDEX).as_deref(),
+                );
+                Some(ChildNode {
+                    subsystem,
+                    devnode,
+                    kind,
+                    interface_number,
+                    links,
+                })
+            })
+            .collect();
+
+        // Enumeration order is not guaranteed, sort by interface then node
+        // 枚举顺序不固定, 按接口再按节点排序
+        children.sort_by(|a, b| {
+            (a.interface_number, &a.devnode).cmp(&(b.interface_number, &b.devnode))
+        });
+        children
+    }
 
-        // Set matching criteria: The subsystem type is "tty".
-        // 设置匹配条件: 子系统的类型是 "tty"
-        enumerator.match_subsystem("tty").ok()?;
+    // Walk the usb_interface children, collect their classes and bound drivers
+    // 遍历 usb_interface 子设备, 收集接口类与绑定的驱动
+    fn find_interfaces<D: SysDevice>(usb_dev: &D) -> (Vec<u8>, Vec<String>) {
+        let mut classes = vec![];
+        let mut drivers = vec![];
 
-        // Set matching criteria: It must be a child device of the current usb_dev.
-        // 设置匹配条件: 必须是当前 usb_dev 的子设备
-        enumerator.match_parent(usb_dev).ok()?;
+        let interfaces = Self::own_descendants(usb_dev)
+            .into_iter()
+            .filter(|child| child.devtype().as_deref() == Some("usb_interface"));
+        for child in interfaces {
+            if let Some(class) =
+                Self::hex_attribute(&child, B_INTERFACE_CLASS).and_then(|c| u8::try_from(c).ok())
+                && !classes.contains(&class)
+            {
+                classes.push(class);
+            }
 
-        // Traversal results
-        // 遍历结果
-        for child in enumerator.scan_devices().ok()? {
-            if let Some(devnode) = child.devnode()
-                && let Some(path) = devnode.to_str()
+            if let Some(driver) = child.driver()
+                && !drivers.contains(&driver)
             {
-                return Some(path.to_string());
+                drivers.push(driver);
             }
         }
 
-        None
+        (classes, drivers)
+    }
+
+    // Read a sysfs attribute written in hex (bcdDevice, bDeviceClass ...)
+    // 读取以十六进制书写的 sysfs 属性 (bcdDevice、bDeviceClass ...)
+    fn hex_attribute<D: SysDevice>(dev: &D, name: &str) -> Option<u16> {
+        let value = dev.attribute(name)?;
+        u16::from_str_radix(value.trim(), 16).ok()
+    }
+
+    // Read a string attribute, fall back to a udev property
+    // 读取字符串属性, 读不到则回退到 udev 属性
+    fn string_attribute<D: SysDevice>(dev: &D, attribute: &str, property: &str) -> Option<String> {
+        dev.attribute(attribute)
+            .or_else(|| dev.property(property))
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
     }
 
-    // parse device
-    // 解析设备
-    fn parse_device(dev: &Device) -> Option<RawDeviceInfo> {
+    // parse device (from libudev, or from a sysfs tree via `SysfsEnumerator`)
+    // 解析设备 (来自 libudev, 或通过 `SysfsEnumerator` 来自 sysfs 目录树)
+    pub(crate) fn parse_device<D: SysDevice>(dev: &D) -> Option<RawDeviceInfo> {
         // Only devices with the "USB" subsystem have VID/PID.
         // 只有 "usb" 子系统的设备才有 VID/PID
-        if dev.subsystem().and_then(|s| s.to_str()) != Some("usb") {
+        if dev.subsystem().as_deref() != Some("usb") {
             return None;
         }
 
         // Only devices of type "usb_device" are considered physical devices (excluding usb_interface).
         // 只有 "usb_device" 类型才算物理设备 (排除 usb_interface)
-        if dev.devtype().and_then(|s| s.to_str()) != Some("usb_device") {
+        if dev.devtype().as_deref() != Some("usb_device") {
             return None;
         }
 
-        // Read VID/PID
-        // 读取 VID / PID
-        let vid = if let Some(v) = dev.property_value(ID_VENDOR_ID) {
-            let s = v.to_str().unwrap_or("");
-            u16::from_str_radix(s, 16).ok()?
-        } else {
-            let v = dev
-                .attribute_value(ID_VENDOR)
-                .and_then(|s| s.to_str())
-                .unwrap_or("");
-            u16::from_str_radix(v, 16).ok()?
-        };
-        let pid = if let Some(p) = dev.property_value(ID_MODEL_ID) {
-            let s = p.to_str().unwrap_or("");
-            u16::from_str_radix(s, 16).ok()?
-        } else {
-            let p = dev
-                .attribute_value(ID_PRODUCT)
-                .and_then(|s| s.to_str())
-                .unwrap_or("");
-            u16::from_str_radix(p, 16).ok()?
-        };
+        // Read VID/PID, the udev property first, then the sysfs attribute
+        // 读取 VID / PID, 先读 udev 属性, 再读 sysfs 属性
+        let vid = dev
+            .property(ID_VENDOR_ID)
+            .or_else(|| dev.attribute(ID_VENDOR))
+            .unwrap_or_default();
+        let vid = u16::from_str_radix(&vid, 16).ok()?;
+        let pid = dev
+            .property(ID_MODEL_ID)
+            .or_else(|| dev.attribute(ID_PRODUCT))
+            .unwrap_or_default();
+        let pid = u16::from_str_radix(&pid, 16).ok()?;
 
         // Read Serial
         // 读取 Serial
         let serial = dev
-            .property_value(ID_SERIAL_SHORT)
-            .and_then(|s| s.to_str())
-            .map(|s| s.to_string())
-            .or_else(|| {
-                dev.attribute_value(USB_SERIAL)
-                    .and_then(|s| s.to_str())
-                    .map(|s| s.to_string())
-            });
+            .property(ID_SERIAL_SHORT)
+            .or_else(|| dev.attribute(USB_SERIAL));
 
         // 物理端口路径
-        let port_path = dev
-            .property_value(ID_PATH)
-            .and_then(|s| s.to_str())
-            .unwrap_or("N/A")
-            .to_string();
+        let port_path = dev.property(ID_PATH).unwrap_or_else(|| "N/A".to_string());
 
         // The original bus path (/dev/bus/usb/001/005)
         // 原始的总线路径 (/dev/bus/usb/001/005)
-        let syspath = dev.syspath().to_str()?.to_string();
+        let syspath = dev.syspath()?;
 
-        // Locate the TTY path
-        // 查找 TTY 路径
-        let tty_path = Self::find_tty_node(dev);
+        // Child device nodes. The alternative path is the first tty, or for a camera without
+        // one, the first capture node
+        // 子设备节点。备用路径取第一个 tty; 没有 tty 的摄像头取第一个视频采集节点
+        let children = Self::find_children(dev);
+        let tty_path = [NodeKind::Serial, NodeKind::Capture]
+            .iter()
+            .find_map(|&kind| children.iter().find(|c| c.kind == kind))
+            .map(|c| c.devnode.clone());
+
+        // Descriptor strings and classes
+        // 描述符字符串与类代码
+        let (interface_classes, drivers) = Self::find_interfaces(dev);
 
         Some(RawDeviceInfo {
             vid,
@@ -147,74 +250,202 @@ impl LinuxMonitor {
             port_path,
             system_path: syspath,      // primary key: /sys/devices/...
             system_path_alt: tty_path, // Actual path: /dev/ttyUSB0
+            manufacturer: Self::string_attribute(dev, USB_MANUFACTURER, ID_VENDOR_PROP),
+            product: Self::string_attribute(dev, USB_PRODUCT, ID_MODEL_PROP),
+            bcd_device: Self::hex_attribute(dev, BCD_DEVICE),
+            device_class: Self::hex_attribute(dev, B_DEVICE_CLASS)
+                .and_then(|c| u8::try_from(c).ok()),
+            interface_classes,
+            drivers,
+            children,
         })
     }
-}
 
-impl DeviceMonitor for LinuxMonitor {
-    fn start(&self, tx: Sender<DeviceEvent>) -> Result<()> {
-        if let Ok(devices) = self.scan_now() {
-            for device in devices {
-                tx.send(DeviceEvent::Attached(device)).ok();
-            }
+    // Create a udev monitor socket for the usb subsystem and the child node subsystems
+    // 创建 usb 子系统与子节点所属子系统的 udev 监听 socket
+    fn open_socket() -> Result<MonitorSocket, MonitorError> {
+        // Create a Builder and configure filtering rules.
+        // 创建 Builder, 配置过滤规则
+        let mut builder = udev::MonitorBuilder::new()
+            // .and_then(|b| b.match_subsystem_devtype("usb", "usb_device"))
+            .and_then(|b| b.match_subsystem("usb"));
+        // tty, video and hidraw nodes are created after the usb_device add event
+        // tty、video 与 hidraw 节点在 usb_device 的 add 事件之后才创建
+        for subsystem in NODE_SUBSYSTEMS {
+            builder = builder.and_then(|b| b.match_subsystem(subsystem));
         }
+        builder
+            .and_then(|b| b.listen())
+            .map_err(|e| MonitorError::from_io("failed to open the udev socket", &e))
+    }
 
-        thread::spawn(move || {
-            // Create a Builder and configure filtering rules.
-            // 创建 Builder, 配置过滤规则
-            let builder = match udev::MonitorBuilder::new()
-                // .and_then(|b| b.match_subsystem_devtype("usb", "usb_device"))
-                .and_then(|b| b.match_subsystem("usb"))
+    // Call `drain` whenever the socket is readable, until a stop request (Ok) or a failure (Err)
+    // 每当 socket 可读时调用 `drain`, 直到收到停止请求 (Ok) 或出错 (Err)
+    fn listen(
+        udev_fd: RawFd,
+        wake: Option<&WakePipe>,
+        stop: &StopSignal,
+        mut drain: impl FnMut() -> Result<(), MonitorError>,
+    ) -> Result<(), MonitorError> {
+        loop {
+            match wait_readable(udev_fd, wake)
+                .map_err(|e| MonitorError::from_io("udev socket failed", &e))?
             {
-                Ok(b) => b,
-                Err(e) => {
-                    info!("[Error] Failed to create udev builder: {:?}", e);
-                    return;
-                }
-            };
+                Readiness::Udev => drain()?,
+                Readiness::Woken => return Ok(()),
+                // Without a wake-up pipe this is the only place a stop request is seen
+                // 没有唤醒管道时, 只有在这里才能发现停止请求
+                Readiness::Timeout if stop.is_stopped() => return Ok(()),
+                Readiness::Timeout => {}
+            }
+        }
+    }
+
+    // Forward the queued udev events
+    // 转发排队中的 udev 事件
+    fn drain(
+        monitor: &MonitorSocket,
+        tracker: &mut DeviceTracker,
+        tx: &Sender<DeviceEvent>,
+    ) -> Result<(), MonitorError> {
+        // The socket is non-blocking, `iter` drains whatever is queued
+        // socket 是非阻塞的, `iter` 会取完当前排队的所有事件
+        for event in monitor.iter() {
+            if let Some(event) = Self::track_event(tracker, &event) {
+                send(tx, event)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Turn one udev event into at most one device event
+    // 把一个 udev 事件转换为至多一个设备事件
+    fn track_event(tracker: &mut DeviceTracker, event: &udev::Event) -> Option<DeviceEvent> {
+        let device = event.device();
+        let action = device.action().and_then(|a| a.to_str()).unwrap_or_default();
+
+        // Every usb, tty, video4linux and hidraw event passes here, so only at trace level
+        // usb、tty、video4linux 与 hidraw 的每个事件都会经过这里, 因此只在 trace 级别输出
+        trace!("[Event] {} -> {:?}", action, device.syspath());
+
+        let tracking = classify(
+            action,
+            SysDevice::subsystem(&device).as_deref(),
+            SysDevice::devtype(&device).as_deref(),
+        );
+        let syspath = SysDevice::syspath(&device)?;
+        tracker.apply(tracking, &syspath, || match tracking {
+            Tracking::Reread => device
+                .parent_with("usb", "usb_device")
+                .and_then(|parent| Self::parse_device(&parent)),
+            _ => Self::parse_device(&device),
+        })
+    }
 
-            let monitor = match builder.listen() {
-                Ok(m) => m,
+    // Diff a fresh scan against what was reported before (nothing, on the first connection)
+    // 用一次新的扫描结果与之前上报过的设备做差异比较 (首次连接时之前没有上报过任何设备)
+    fn resync(tracker: &mut DeviceTracker, tx: &Sender<DeviceEvent>) -> Result<(), MonitorError> {
+        let devices = LinuxMonitor::new()
+            .scan_now()
+            .map_err(|e| MonitorError::Enumeration(format!("{:#}", e)))?;
+        for event in tracker.resync(devices) {
+            send(tx, event)?;
+        }
+        Ok(())
+    }
+}
+
+// Send an event, a dropped receiver ends the listener thread
+// 发送事件, 接收端被 drop 时监听线程随之结束
+fn send(tx: &Sender<DeviceEvent>, event: DeviceEvent) -> Result<(), MonitorError> {
+    tx.send(event).map_err(|_| MonitorError::ChannelClosed)
+}
+
+impl DeviceMonitor for LinuxMonitor {
+    fn start(&self, tx: Sender<DeviceEvent>) -> Result<MonitorHandle> {
+        // Remember what was reported, so a reconnect can resync against it
+        // 记录已上报的设备, 重连后据此重新同步
+        let mut tracker = DeviceTracker::default();
+
+        let handle = MonitorHandle::spawn("udev-monitor", move |stop| {
+            // Without a wake-up pipe, fall back to checking the stop flag periodically
+            // 没有唤醒管道时, 退化为定期检查停止标志
+            let wake = match WakePipe::new() {
+                Ok((wake, waker)) => {
+                    stop.on_stop(move || waker.wake());
+                    Some(wake)
+                }
                 Err(e) => {
-                    info!("[Error] Failed to listen to udev socket: {:?}", e);
-                    return;
+                    info!("[Linux] No wake-up pipe ({}), polling the stop flag.", e);
+                    None
                 }
             };
 
-            info!("[Linux] The udev listener thread has been started.");
+            // Keep-Alive loop: rebuild the udev connection whenever it fails
+            // Keep-Alive 循环: udev 连接失败时自动重建
+            let mut backoff = Backoff::default();
+            let mut outage = false;
+            let mut first = true;
+            while !stop.is_stopped() {
+                let result = Self::open_socket().and_then(|monitor| {
+                    if first && !outage {
+                        // Subscribe before scanning: a device that appears in between is in
+                        // both the scan and the socket, and the tracker reports it once
+                        // 先订阅再扫描: 在两者之间出现的设备同时出现在扫描结果与 socket 中,
+                        // tracker 只会上报一次
+                        if let Err(error) = Self::resync(&mut tracker, &tx) {
+                            if error == MonitorError::ChannelClosed {
+                                return Err(error);
+                            }
+                            warn!("[Linux] {}", error);
+                            send(&tx, DeviceEvent::MonitorError(error))?;
+                        }
+                    }
+                    first = false;
+                    if outage {
+                        // Events may have been missed while disconnected
+                        // 断开期间可能漏掉了事件
+                        Self::resync(&mut tracker, &tx)?;
+                        send(&tx, DeviceEvent::MonitorRecovered)?;
+                        stop.report_ok();
+                        info!("[Linux] The udev connection has been recovered.");
+                        outage = false;
+                    }
+                    backoff.reset();
+                    info!("[Linux] The udev listener thread has been started.");
 
-            loop {
-                for event in monitor.iter() {
-                    let event_type = event.event_type();
-                    let device = event.device();
+                    Self::listen(monitor.as_raw_fd(), wake.as_ref(), &stop, || {
+                        Self::drain(&monitor, &mut tracker, &tx)
+                    })
+                });
 
-                    // Debug: 看看到底收到了什么事件
-                    info!("[Event] {:?} -> {:?}", event_type, device.syspath());
+                let error = match result {
+                    Ok(()) => break,
+                    Err(MonitorError::ChannelClosed) => {
+                        stop.report_error(MonitorError::ChannelClosed);
+                        break;
+                    }
+                    Err(error) => error,
+                };
 
-                    match event.event_type() {
-                        // Insertion event
-                        // 插入事件
-                        EventType::Add => {
-                            if let Some(dev) = Self::parse_device(&event.device()) {
-                                tx.send(DeviceEvent::Attached(dev)).ok();
-                            }
-                        }
-                        // Remove event
-                        // 移除事件
-                        EventType::Remove => {
-                            if let Some(path_str) = event.device().syspath().to_str() {
-                                tx.send(DeviceEvent::Detached(path_str.to_string())).ok();
-                            }
-                        }
-                        _ => {}
+                // Report an outage once, not on every retry
+                // 一次中断只上报一次, 而不是每次重试都上报
+                if !outage {
+                    warn!("[Linux] {}, reconnecting.", error);
+                    stop.report_error(error.clone());
+                    if send(&tx, DeviceEvent::MonitorError(error)).is_err() {
+                        stop.report_error(MonitorError::ChannelClosed);
+                        break;
                     }
+                    outage = true;
                 }
-
-                std::thread::sleep(Duration::from_millis(200));
+                stop.wait_timeout(backoff.next());
             }
-        });
 
-        Ok(())
+            info!("[Linux] The udev listener thread has been stopped.");
+        })?;
+
+        Ok(handle)
     }
 
     fn scan_now(&self) -> Result<Vec<RawDeviceInfo>> {
@@ -244,3 +475,873 @@ impl DeviceMonitor for LinuxMonitor {
         Ok(devices)
     }
 }
+
+// The udev socket driven by the tokio reactor instead of a listener thread
+// 由 tokio reactor 驱动的 udev socket, 不需要监听线程
+#[cfg(feature = "tokio")]
+pub(crate) struct UdevStream {
+    socket: AsyncFd<UdevSocket>,
+    tracker: DeviceTracker,
+    pending: VecDeque<DeviceEvent>,
+    failed: bool,
+}
+
+// libudev objects are not thread-safe, but they may move between threads: the stream owns the
+// socket and everything created from it, and only touches them from `poll_next`
+// libudev 对象不是线程安全的, 但可以在线程间移动: stream 独占 socket 及由它创建的所有对象,
+// 并且只在 `poll_next` 中访问它们
+#[cfg(feature = "tokio")]
+struct UdevSocket(MonitorSocket);
+
+// SAFETY: libudev objects are `!Send` because their reference counts (on the device, the monitor
+// and the shared `udev` context) are not atomic, not because they are tied to a thread. Moving
+// them is sound as long as no other handle to the same objects stays behind on another thread:
+// - the socket and its context are created in `UdevStream::open` and never cloned out of it;
+//   `scan_now` there builds its own `Enumerator` with a separate context
+// - the `SocketIter` and the `Device`s it yields hold extra references to the context, but they
+//   only live inside one `poll_next` call, so they move together with the socket or not at all
+// - the tokio reactor only sees the raw fd through `AsRawFd`, never the libudev objects
+// `UdevSocket` is not `Sync`, so the stream can't touch it from two threads at once.
+// SAFETY: libudev 对象不是 `Send`, 原因是它们 (设备、monitor 与共享的 `udev` 上下文) 的引用计数
+// 不是原子的, 而不是它们与某个线程绑定。只要没有指向同一对象的句柄留在其他线程上, 移动它们就是
+// 安全的:
+// - socket 及其上下文在 `UdevStream::open` 中创建, 从不被克隆出去; 其中的 `scan_now` 使用自己的
+//   `Enumerator` 与独立的上下文
+// - `SocketIter` 及其产生的 `Device` 会额外引用上下文, 但它们只存活于一次 `poll_next` 调用之内,
+//   因此要么与 socket 一起移动, 要么根本不会被移动
+// - tokio reactor 只通过 `AsRawFd` 看到原始 fd, 从不接触 libudev 对象
+// `UdevSocket` 不是 `Sync`, 因此 stream 不会同时在两个线程上访问它。
+#[cfg(feature = "tokio")]
+unsafe impl Send for UdevSocket {}
+
+#[cfg(feature = "tokio")]
+impl AsRawFd for UdevSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl UdevStream {
+    // Must be called inside a tokio runtime with I/O enabled
+    // 必须在启用了 I/O 的 tokio 运行时中调用
+    pub(crate) fn open() -> Result<Self, MonitorError> {
+        let socket = LinuxMonitor::open_socket()?;
+        let socket = AsyncFd::with_interest(UdevSocket(socket), Interest::READABLE)
+            .map_err(|e| MonitorError::from_io("failed to register the udev socket", &e))?;
+
+        // Like the listener thread, start by reporting what is present
+        // 与监听线程一样, 先上报当前在线的设备
+        let mut tracker = DeviceTracker::default();
+        let devices = LinuxMonitor::new()
+            .scan_now()
+            .map_err(|e| MonitorError::Enumeration(format!("{:#}", e)))?;
+        let pending = tracker.resync(devices).into();
+
+        Ok(Self {
+            socket,
+            tracker,
+            pending,
+            failed: false,
+        })
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Stream for UdevStream {
+    type Item = DeviceEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<DeviceEvent>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(event) = this.pending.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+            // A failed socket ends the stream after its MonitorError
+            // socket 出错时, 在发出 MonitorError 之后结束 stream
+            if this.failed {
+                return Poll::Ready(None);
+            }
+
+            let mut guard = match this.socket.poll_read_ready(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(e)) => {
+                    this.failed = true;
+                    let error = MonitorError::from_io("udev socket failed", &e);
+                    return Poll::Ready(Some(DeviceEvent::MonitorError(error)));
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+            for event in guard.get_inner().0.iter() {
+                if let Some(event) = LinuxMonitor::track_event(&mut this.tracker, &event) {
+                    this.pending.push_back(event);
+                }
+            }
+            guard.clear_ready();
+        }
+    }
+}
+
+// libudev's view of a device
+// libudev 提供的设备视图
+impl SysDevice for Device {
+    fn syspath(&self) -> Option<String> {
+        Some(Device::syspath(self).to_str()?.to_string())
+    }
+
+    fn subsystem(&self) -> Option<String> {
+        Some(Device::subsystem(self)?.to_str()?.to_string())
+    }
+
+    fn devtype(&self) -> Option<String> {
+        Some(Device::devtype(self)?.to_str()?.to_string())
+    }
+
+    fn devnode(&self) -> Option<String> {
+        Some(Device::devnode(self)?.to_str()?.to_string())
+    }
+
+    fn driver(&self) -> Option<String> {
+        Some(Device::driver(self)?.to_str()?.to_string())
+    }
+
+    fn attribute(&self, name: &str) -> Option<String> {
+        Some(self.attribute_value(name)?.to_str()?.to_string())
+    }
+
+    fn property(&self, name: &str) -> Option<String> {
+        Some(self.property_value(name)?.to_str()?.to_string())
+    }
+
+    fn parent_with(&self, subsystem: &str, devtype: &str) -> Option<Self> {
+        self.parent_with_subsystem_devtype(subsystem, devtype)
+            .ok()
+            .flatten()
+    }
+
+    fn descendants(&self) -> Vec<Self> {
+        // Create an enumerator to iterate through the sub-devices.
+        // 创建一个枚举器, 用于遍历子设备
+        let Ok(mut enumerator) = Enumerator::new() else {
+            return vec![];
+        };
+
+        // Set matching criteria: It must be a child device of the current device.
+        // 设置匹配条件: 必须是当前设备的子设备
+        if enumerator.match_parent(self).is_err() {
+            return vec![];
+        }
+        let Ok(devices) = enumerator.scan_devices() else {
+            return vec![];
+        };
+
+        // The device itself matches too
+        // 设备本身也会被匹配到
+        devices
+            .filter(|child| Device::syspath(child) != Device::syspath(self))
+            .collect()
+    }
+}
+
+// Turns raw udev add/remove events into one event per `usb_device`
+// 把原始的 udev add/remove 事件整理为每个 `usb_device` 一个事件
+//
+// The usb subsystem also reports every interface of a device, and one unplug yields a remove
+// for each of them. Only syspaths that were reported as `Attached` produce a `Detached`.
+// Child nodes (a serial adapter's tty, a camera's /dev/videoN, hidraw) usually appear after
+// the `usb_device` add event, so a later node add re-reads the device and reports the change as
+// `Updated`. A node remove does the same: a driver can unbind while the device stays plugged in.
+// usb 子系统同样会上报设备的每个接口, 一次拔出会为每个接口产生一个 remove 事件。
+// 只有上报过 `Attached` 的 syspath 才会产生 `Detached`。
+// 子节点 (串口适配器的 tty、摄像头的 /dev/videoN、hidraw) 通常在 `usb_device` 的 add 事件之后
+// 才出现, 因此之后的节点 add 会重新读取设备, 并以 `Updated` 上报变化。节点的 remove 同样如此:
+// 驱动可能在设备仍然插着时解绑。
+#[derive(Debug, Default)]
+struct DeviceTracker {
+    known: HashMap<String, RawDeviceInfo>,
+}
+
+// What a udev event asks of the tracker
+// 一个 udev 事件需要 tracker 做什么
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tracking {
+    // A usb_device appeared: parse it
+    // 出现了一个 usb_device: 解析它
+    Add,
+    // A child node appeared or went away: re-read the usb_device it belongs to
+    // 出现或移除了一个子节点: 重新读取它所属的 usb_device
+    Reread,
+    // Something went away: a `Detached` if it was reported before
+    // 某个设备消失: 如果之前上报过则产生 `Detached`
+    Remove,
+    Ignore,
+}
+
+// Decide what a udev event means, from its action, subsystem and devtype alone
+// 仅根据 action、subsystem 与 devtype 判断一个 udev 事件的含义
+fn classify(action: &str, subsystem: Option<&str>, devtype: Option<&str>) -> Tracking {
+    let is_node = subsystem.is_some_and(|s| NODE_SUBSYSTEMS.contains(&s));
+    match action {
+        // A node remove is either part of an unplug, where the usb_device can no longer be read
+        // or its remove follows, or a driver unbinding (`modprobe -r cdc_acm`) from a device
+        // that stays plugged in
+        // 节点的 remove 要么属于一次拔出 (此时 usb_device 已无法读取, 或者它的 remove 紧随其后),
+        // 要么是驱动从仍然插着的设备上解绑 (`modprobe -r cdc_acm`)
+        "add" | "remove" if is_node => Tracking::Reread,
+        _ if is_node => Tracking::Ignore,
+        // Interfaces and endpoints are added along with their device
+        // 接口与端点随设备一起添加
+        "add" if devtype == Some("usb_device") => Tracking::Add,
+        // Interfaces and other children are filtered by the tracker
+        // 接口等子设备由 tracker 过滤
+        "remove" => Tracking::Remove,
+        _ => Tracking::Ignore,
+    }
+}
+
+impl DeviceTracker {
+    // Carry out a classified event at `syspath`. `read` parses the device to add, or the
+    // usb_device to re-read, and is only called when needed.
+    // 执行一个已分类的事件。`read` 解析要添加的设备或要重新读取的 usb_device, 只在需要时调用。
+    fn apply(
+        &mut self,
+        tracking: Tracking,
+        syspath: &str,
+        read: impl FnOnce() -> Option<RawDeviceInfo>,
+    ) -> Option<DeviceEvent> {
+        match tracking {
+            Tracking::Add => self.add(read()),
+            Tracking::Reread => self.update(read()),
+            Tracking::Remove => self.remove(syspath),
+            Tracking::Ignore => None,
+        }
+    }
+
+    // `device` is the parsed add event, None for interfaces and unreadable devices
+    // `device` 是解析后的 add 事件, 接口或无法读取的设备为 None
+    fn add(&mut self, device: Option<RawDeviceInfo>) -> Option<DeviceEvent> {
+        let device = device?;
+        // A repeated add (e.g. after a resync) is not a new device
+        // 重复的 add (例如重新同步之后) 不是新设备
+        if self.known.contains_key(&device.system_path) {
+            return self.update(Some(device));
+        }
+        self.known
+            .insert(device.system_path.clone(), device.clone());
+        Some(DeviceEvent::Attached(device))
+    }
+
+    // A fresh reading of a known device, reported only if something changed
+    // 已知设备的最新信息, 只有发生变化时才上报
+    fn update(&mut self, device: Option<RawDeviceInfo>) -> Option<DeviceEvent> {
+        let device = device?;
+        let known = self.known.get_mut(&device.system_path)?;
+        if *known == device {
+            return None;
+        }
+        *known = device.clone();
+        Some(DeviceEvent::Updated(device))
+    }
+
+    fn remove(&mut self, syspath: &str) -> Option<DeviceEvent> {
+        self.known
+            .remove(syspath)
+            .map(|_| DeviceEvent::Detached(syspath.to_string()))
+    }
+
+    // Synthetic events that bring the tracker in line with `current`
+    // 生成补发事件, 使 tracker 与 `current` 保持一致
+    fn resync(&mut self, current: Vec<RawDeviceInfo>) -> Vec<DeviceEvent> {
+        let current_paths: HashSet<&str> = current.iter().map(|d| d.system_path.as_str()).collect();
+
+        let mut gone: Vec<String> = self
+            .known
+            .keys()
+            .filter(|path| !current_paths.contains(path.as_str()))
+            .cloned()
+            .collect();
+        gone.sort();
+        let mut events: Vec<DeviceEvent> = gone
+            .into_iter()
+            .filter_map(|path| self.remove(&path))
+            .collect();
+
+        events.extend(current.into_iter().filter_map(|d| self.add(Some(d))));
+        events
+    }
+}
+
+// What a child node is for. udev's v4l_id reports ":capture:" for nodes that deliver frames;
+// without it, UVC creates the capture node first (index 0) and the metadata node second.
+// 子设备节点的用途。udev 的 v4l_id 会为能输出画面的节点标记 ":capture:";
+// 没有该属性时, UVC 总是先创建采集节点 (index 0), 再创建元数据节点。
+fn node_kind(subsystem: &str, v4l_capabilities: Option<&str>, v4l_index: Option<&str>) -> NodeKind {
+    match subsystem {
+        "tty" => NodeKind::Serial,
+        "video4linux" => {
+            let capture = match v4l_capabilities {
+                Some(capabilities) => capabilities.split(':').any(|c| c == "capture"),
+                None => v4l_index.is_none_or(|index| index.trim() == "0"),
+            };
+            if capture {
+                NodeKind::Capture
+            } else {
+                NodeKind::Metadata
+            }
+        }
+        _ => NodeKind::Other,
+    }
+}
+
+// Reconnect delays: doubling from 500ms up to 30s
+// 重连间隔: 从 500ms 开始翻倍, 最长 30s
+struct Backoff {
+    delay: Duration,
+}
+
+impl Backoff {
+    const MIN: Duration = Duration::from_millis(500);
+    const MAX: Duration = Duration::from_secs(30);
+
+    fn next(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (self.delay * 2).min(Self::MAX);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.delay = Self::MIN;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self { delay: Self::MIN }
+    }
+}
+
+// Poll interval used when there is no wake-up pipe
+// 没有唤醒管道时使用的轮询间隔
+const STOP_POLL_MS: i32 = 200;
+
+// Why `wait_readable` returned
+// `wait_readable` 返回的原因
+enum Readiness {
+    Udev,
+    Woken,
+    Timeout,
+}
+
+// Block until the udev socket is readable or the wake-up pipe is written to
+// 阻塞直到 udev socket 可读, 或唤醒管道被写入
+fn wait_readable(udev_fd: RawFd, wake: Option<&WakePipe>) -> io::Result<Readiness> {
+    let mut fds = [
+        libc::pollfd {
+            fd: udev_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: wake.map_or(-1, |w| w.read.as_raw_fd()),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    let timeout = if wake.is_some() { -1 } else { STOP_POLL_MS };
+
+    loop {
+        let n = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        if n >= 0 {
+            break;
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+
+    if fds[1].revents != 0 {
+        Ok(Readiness::Woken)
+    } else if fds[0].revents & (libc::POLLERR | libc::POLLHUP | libc::POLLNVAL) != 0 {
+        Err(io::Error::other("udev socket closed"))
+    } else if fds[0].revents != 0 {
+        Ok(Readiness::Udev)
+    } else {
+        Ok(Readiness::Timeout)
+    }
+}
+
+// Read end of a self-pipe, polled next to the udev socket
+// 自管道的读端, 与 udev socket 一起被 poll
+struct WakePipe {
+    read: OwnedFd,
+}
+
+// Write end of the self-pipe, handed to the stop signal
+// 自管道的写端, 交给停止信号使用
+struct Waker {
+    write: Mutex<File>,
+}
+
+impl WakePipe {
+    fn new() -> io::Result<(Self, Waker)> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: pipe2 just returned two fresh descriptors that nothing else owns
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        Ok((
+            Self { read },
+            Waker {
+                write: Mutex::new(write),
+            },
+        ))
+    }
+}
+
+impl Waker {
+    fn wake(&self) {
+        // A full pipe already guarantees a wake-up, so errors can be ignored
+        // 管道已满时同样能保证唤醒, 因此可以忽略错误
+        let _ = self.write.lock().unwrap().write(&[1]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_readable_reports_udev_and_wake_ups() {
+        // A second pipe stands in for the udev socket
+        // 用另一个管道代替 udev socket
+        let (udev, udev_waker) = WakePipe::new().unwrap();
+        let (wake, waker) = WakePipe::new().unwrap();
+
+        udev_waker.wake();
+        assert!(matches!(
+            wait_readable(udev.read.as_raw_fd(), Some(&wake)),
+            Ok(Readiness::Udev)
+        ));
+
+        waker.wake();
+        assert!(matches!(
+            wait_readable(udev.read.as_raw_fd(), Some(&wake)),
+            Ok(Readiness::Woken)
+        ));
+    }
+
+    #[test]
+    fn wait_readable_times_out_without_wake_pipe() {
+        let (udev, _udev_waker) = WakePipe::new().unwrap();
+        assert!(matches!(
+            wait_readable(udev.read.as_raw_fd(), None),
+            Ok(Readiness::Timeout)
+        ));
+    }
+
+    #[test]
+    fn listen_without_wake_pipe_stops_on_the_next_timeout() {
+        let (udev, udev_waker) = WakePipe::new().unwrap();
+        let (tx, rx) = crossbeam_channel::unbounded();
+
+        let handle = MonitorHandle::spawn("listen-test", move |stop| {
+            let fd = udev.read.as_raw_fd();
+            let result = LinuxMonitor::listen(fd, None, &stop, || {
+                let mut buf = [0u8; 16];
+                // SAFETY: `fd` is the open read end of the pipe and `buf` is a valid buffer
+                unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+                tx.send("drained").map_err(|_| MonitorError::ChannelClosed)
+            });
+            let _ = tx.send(if result.is_ok() { "stopped" } else { "failed" });
+        })
+        .unwrap();
+
+        udev_waker.wake();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok("drained"));
+
+        let started = std::time::Instant::now();
+        drop(handle);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(rx.try_recv(), Ok("stopped"));
+    }
+
+    fn device(path: &str) -> RawDeviceInfo {
+        RawDeviceInfo {
+            system_path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn summary(events: impl IntoIterator<Item = DeviceEvent>) -> Vec<String> {
+        events
+            .into_iter()
+            .map(|e| match e {
+                DeviceEvent::Attached(dev) => format!("+{}", dev.system_path),
+                DeviceEvent::Detached(path) => format!("-{}", path),
+                DeviceEvent::Updated(dev) => format!(
+                    "~{} {}",
+                    dev.system_path,
+                    dev.system_path_alt.unwrap_or_default()
+                ),
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect()
+    }
+
+    // Feed a recorded udev sequence (action, subsystem, devtype, syspath) through `classify`
+    // and `apply`, like `track_event` does. `read` stands in for sysfs: the device an add
+    // parses, or the usb_device a node event re-reads.
+    // 把一段录制的 udev 事件序列 (action, subsystem, devtype, syspath) 交给 `classify` 与
+    // `apply` 处理, 与 `track_event` 相同。`read` 代替 sysfs: 返回 add 解析出的设备,
+    // 或节点事件重新读取的 usb_device。
+    fn replay(
+        tracker: &mut DeviceTracker,
+        recording: &[(&str, &str, &str, &str)],
+        read: impl Fn(&str) -> Option<RawDeviceInfo>,
+    ) -> Vec<String> {
+        let events: Vec<DeviceEvent> = recording
+            .iter()
+            .filter_map(|&(action, subsystem, devtype, syspath)| {
+                let devtype = (!devtype.is_empty()).then_some(devtype);
+                let tracking = classify(action, Some(subsystem), devtype);
+                tracker.apply(tracking, syspath, || read(syspath))
+            })
+            .collect();
+        summary(events)
+    }
+
+    // What sysfs holds for a plain device: no child nodes yet
+    // 普通设备在 sysfs 中的信息: 还没有子节点
+    fn read_plain(syspath: &str) -> Option<RawDeviceInfo> {
+        Some(device(syspath))
+    }
+
+    const DEV: &str = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2";
+    const IF0: &str = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0";
+    const IF1: &str = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.1";
+    const TTY: &str = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/ttyUSB0/tty/ttyUSB0";
+
+    #[test]
+    fn one_detach_per_unplugged_device() {
+        // A dual-interface serial adapter plugged in and out (udevadm monitor --kernel)
+        // 插入再拔出一个双接口串口适配器 (udevadm monitor --kernel)
+        let recording = [
+            ("add", "usb", "usb_device", DEV),
+            ("add", "usb", "usb_interface", IF0),
+            ("add", "usb", "usb_interface", IF1),
+            ("bind", "usb", "usb_interface", IF0),
+            ("bind", "usb", "usb_interface", IF1),
+            ("bind", "usb", "usb_device", DEV),
+            ("unbind", "usb", "usb_interface", IF0),
+            ("remove", "usb", "usb_interface", IF0),
+            ("unbind", "usb", "usb_interface", IF1),
+            ("remove", "usb", "usb_interface", IF1),
+            ("unbind", "usb", "usb_device", DEV),
+            ("remove", "usb", "usb_device", DEV),
+        ];
+        let mut tracker = DeviceTracker::default();
+        assert_eq!(
+            replay(&mut tracker, &recording, read_plain),
+            [format!("+{}", DEV), format!("-{}", DEV)]
+        );
+        assert!(tracker.known.is_empty());
+    }
+
+    // The reading of DEV once the given tty nodes exist, as a tty add re-reads it
+    // 给定的 tty 节点出现之后 DEV 的读取结果, 即 tty add 重新读取到的内容
+    fn with_ttys(nodes: &[&str]) -> Option<RawDeviceInfo> {
+        Some(RawDeviceInfo {
+            system_path_alt: nodes.first().map(|n| n.to_string()),
+            children: nodes
+                .iter()
+                .map(|n| ChildNode {
+                    subsystem: "tty".to_string(),
+                    devnode: n.to_string(),
+                    kind: NodeKind::Serial,
+                    ..Default::default()
+                })
+                .collect(),
+            ..device(DEV)
+        })
+    }
+
+    #[test]
+    fn tty_events_are_classified() {
+        assert_eq!(classify("add", Some("tty"), None), Tracking::Reread);
+        assert_eq!(classify("add", Some("video4linux"), None), Tracking::Reread);
+        assert_eq!(classify("add", Some("hidraw"), None), Tracking::Reread);
+        assert_eq!(
+            classify("remove", Some("video4linux"), None),
+            Tracking::Reread
+        );
+        assert_eq!(classify("remove", Some("tty"), None), Tracking::Reread);
+        assert_eq!(classify("change", Some("tty"), None), Tracking::Ignore);
+        assert_eq!(
+            classify("add", Some("usb"), Some("usb_device")),
+            Tracking::Add
+        );
+    }
+
+    #[test]
+    fn late_tty_node_is_reported_as_an_update() {
+        // A CH340 adapter: the tty shows up once the ch341 driver has bound the interface
+        // CH340 适配器: ch341 驱动绑定接口之后 tty 才出现
+        let recording = [
+            ("add", "usb", "usb_device", DEV),
+            ("add", "usb", "usb_interface", IF0),
+            ("bind", "usb", "usb_interface", IF0),
+            ("add", "tty", "", TTY),
+            ("bind", "usb", "usb_device", DEV),
+            ("remove", "tty", "", TTY),
+            ("unbind", "usb", "usb_interface", IF0),
+            ("remove", "usb", "usb_interface", IF0),
+            ("unbind", "usb", "usb_device", DEV),
+            ("remove", "usb", "usb_device", DEV),
+        ];
+        let read = |syspath: &str| match syspath {
+            TTY => with_ttys(&["/dev/ttyUSB0"]),
+            _ => read_plain(syspath),
+        };
+        let mut tracker = DeviceTracker::default();
+        assert_eq!(
+            replay(&mut tracker, &recording, read),
+            [
+                format!("+{}", DEV),
+                format!("~{} /dev/ttyUSB0", DEV),
+                format!("-{}", DEV)
+            ]
+        );
+
+        // A tty of a device that was never attached is ignored
+        // 未上线设备的 tty 被忽略
+        assert!(replay(&mut tracker, &[("add", "tty", "", TTY)], read).is_empty());
+    }
+
+    #[test]
+    fn removed_tty_of_a_plugged_device_is_reported_as_an_update() {
+        // `modprobe -r ch341`: the driver lets go of the interface, the device stays
+        // `modprobe -r ch341`: 驱动释放接口, 设备仍然插着
+        let mut tracker = DeviceTracker::default();
+        let plugged = [
+            ("add", "usb", "usb_device", DEV),
+            ("add", "usb", "usb_interface", IF0),
+            ("add", "tty", "", TTY),
+        ];
+        let read = |syspath: &str| match syspath {
+            TTY => with_ttys(&["/dev/ttyUSB0"]),
+            _ => read_plain(syspath),
+        };
+        replay(&mut tracker, &plugged, read);
+
+        let unloaded = [
+            ("remove", "tty", "", TTY),
+            ("unbind", "usb", "usb_interface", IF0),
+        ];
+        assert_eq!(
+            replay(&mut tracker, &unloaded, |_| read_plain(DEV)),
+            [format!("~{} ", DEV)]
+        );
+        assert!(tracker.known[DEV].children.is_empty());
+        assert_eq!(tracker.known[DEV].system_path_alt, None);
+
+        // On an unplug the usb_device is gone by then, and its own remove detaches it
+        // 拔出时 usb_device 已经无法读取, 由它自己的 remove 产生 Detached
+        let unplugged = [
+            ("remove", "tty", "", TTY),
+            ("remove", "usb", "usb_interface", IF0),
+            ("remove", "usb", "usb_device", DEV),
+        ];
+        assert_eq!(
+            replay(&mut tracker, &unplugged, |_| None),
+            [format!("-{}", DEV)]
+        );
+    }
+
+    #[test]
+    fn every_new_tty_node_updates_the_device() {
+        // A dual-port CDC ACM adapter: two interfaces, two ttys, one after the other
+        // 双口 CDC ACM 适配器: 两个接口, 两个 tty 先后出现
+        const ACM0: &str = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/tty/ttyACM0";
+        const ACM1: &str = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.1/tty/ttyACM1";
+        let recording = [
+            ("add", "usb", "usb_device", DEV),
+            ("add", "usb", "usb_interface", IF0),
+            ("add", "tty", "", ACM0),
+            ("add", "usb", "usb_interface", IF1),
+            ("add", "tty", "", ACM1),
+        ];
+        let read = |syspath: &str| match syspath {
+            ACM0 => with_ttys(&["/dev/ttyACM0"]),
+            ACM1 => with_ttys(&["/dev/ttyACM0", "/dev/ttyACM1"]),
+            _ => read_plain(syspath),
+        };
+        let mut tracker = DeviceTracker::default();
+        assert_eq!(
+            replay(&mut tracker, &recording, read),
+            [
+                format!("+{}", DEV),
+                format!("~{} /dev/ttyACM0", DEV),
+                format!("~{} /dev/ttyACM0", DEV)
+            ]
+        );
+        assert_eq!(tracker.known[DEV].children.len(), 2);
+
+        // A repeated tty add that changes nothing is not reported
+        // 没有带来任何变化的重复 tty add 不会上报
+        assert!(replay(&mut tracker, &[("add", "tty", "", ACM1)], read).is_empty());
+    }
+
+    #[test]
+    fn late_video_nodes_are_reported_as_updates() {
+        // A UVC webcam: uvcvideo creates the capture node, then the metadata node
+        // UVC 摄像头: uvcvideo 先创建采集节点, 再创建元数据节点
+        const CAM: &str = "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-3";
+        const VIDEO0: &str =
+            "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/video4linux/video0";
+        const VIDEO1: &str =
+            "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/video4linux/video1";
+        let recording = [
+            ("add", "usb", "usb_device", CAM),
+            (
+                "add",
+                "usb",
+                "usb_interface",
+                "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0",
+            ),
+            ("add", "video4linux", "", VIDEO0),
+            ("add", "video4linux", "", VIDEO1),
+            ("bind", "usb", "usb_device", CAM),
+        ];
+        let node = |devnode: &str, kind| ChildNode {
+            subsystem: "video4linux".to_string(),
+            devnode: devnode.to_string(),
+            kind,
+            interface_number: Some(0),
+            ..Default::default()
+        };
+        let read = |syspath: &str| {
+            let children = match syspath {
+                VIDEO0 => vec![node("/dev/video0", NodeKind::Capture)],
+                VIDEO1 => vec![
+                    node("/dev/video0", NodeKind::Capture),
+                    node("/dev/video1", NodeKind::Metadata),
+                ],
+                _ => vec![],
+            };
+            Some(RawDeviceInfo {
+                system_path_alt: children.first().map(|c| c.devnode.clone()),
+                children,
+                ..device(CAM)
+            })
+        };
+
+        let mut tracker = DeviceTracker::default();
+        assert_eq!(
+            replay(&mut tracker, &recording, read),
+            [
+                format!("+{}", CAM),
+                format!("~{} /dev/video0", CAM),
+                format!("~{} /dev/video0", CAM)
+            ]
+        );
+        assert_eq!(tracker.known[CAM].children.len(), 2);
+    }
+
+    #[test]
+    fn removes_of_unseen_devices_are_dropped() {
+        let mut tracker = DeviceTracker::default();
+
+        // Only the interfaces' removes arrive, e.g. a driver unloaded by hand
+        // 只收到接口的 remove, 例如手动卸载驱动
+        assert!(
+            replay(
+                &mut tracker,
+                &[("remove", "usb", "usb_interface", IF0)],
+                read_plain
+            )
+            .is_empty()
+        );
+
+        // A device that could not be parsed on add is never detached either
+        // 在 add 时无法解析的设备同样不会产生 detach
+        let recording = [
+            ("add", "usb", "usb_device", DEV),
+            ("add", "usb", "usb_interface", IF0),
+            ("remove", "usb", "usb_interface", IF0),
+            ("remove", "usb", "usb_device", DEV),
+        ];
+        assert!(replay(&mut tracker, &recording, |_| None).is_empty());
+    }
+
+    #[test]
+    fn repeated_events_are_reported_once() {
+        let mut tracker = DeviceTracker::default();
+        tracker.resync(vec![device(DEV)]);
+
+        // An add already covered by the initial scan, then a duplicate remove
+        // 初始扫描已经覆盖的 add, 随后是重复的 remove
+        let recording = [
+            ("add", "usb", "usb_device", DEV),
+            ("remove", "usb", "usb_device", DEV),
+            ("remove", "usb", "usb_device", DEV),
+        ];
+        assert_eq!(
+            replay(&mut tracker, &recording, read_plain),
+            [format!("-{}", DEV)]
+        );
+    }
+
+    #[test]
+    fn resync_reports_missed_changes() {
+        let mut tracker = DeviceTracker::default();
+        tracker.resync(vec![device("/sys/a"), device("/sys/b")]);
+
+        let events = tracker.resync(vec![device("/sys/b"), device("/sys/c")]);
+        assert_eq!(summary(events), ["-/sys/a", "+/sys/c"]);
+        let mut known: Vec<&String> = tracker.known.keys().collect();
+        known.sort();
+        assert_eq!(known, ["/sys/b", "/sys/c"]);
+    }
+
+    #[test]
+    fn video_nodes_are_told_apart() {
+        // A UVC webcam: /dev/video0 delivers frames, /dev/video1 only metadata
+        // UVC 摄像头: /dev/video0 输出画面, /dev/video1 只有元数据
+        assert_eq!(
+            node_kind("video4linux", Some(":capture:"), Some("0")),
+            NodeKind::Capture
+        );
+        assert_eq!(
+            node_kind("video4linux", Some(":"), Some("1")),
+            NodeKind::Metadata
+        );
+
+        // Without v4l_id the node index decides
+        // 没有 v4l_id 时由节点的 index 决定
+        assert_eq!(
+            node_kind("video4linux", None, Some("0\n")),
+            NodeKind::Capture
+        );
+        assert_eq!(
+            node_kind("video4linux", None, Some("1\n")),
+            NodeKind::Metadata
+        );
+
+        assert_eq!(node_kind("tty", None, None), NodeKind::Serial);
+        assert_eq!(node_kind("hidraw", None, None), NodeKind::Other);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        let mut backoff = Backoff::default();
+        let delays: Vec<Duration> = (0..9).map(|_| backoff.next()).collect();
+        assert_eq!(delays[0], Backoff::MIN);
+        assert_eq!(delays[1], Backoff::MIN * 2);
+        assert_eq!(delays[8], Backoff::MAX);
+
+        backoff.reset();
+        assert_eq!(backoff.next(), Backoff::MIN);
+    }
+}
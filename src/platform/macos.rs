@@ -2,16 +2,22 @@ use std::{
     collections::HashSet,
     ffi::{CStr, CString},
     os::raw::c_void,
-    sync::Mutex,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
     thread,
     time::Duration,
 };
 
 use anyhow::{Result, anyhow};
 use core_foundation::{
-    base::{CFRelease, CFTypeRef, kCFAllocatorDefault},
+    base::{CFRelease, CFRetain, CFTypeRef, kCFAllocatorDefault},
     number::{CFNumberGetValue, CFNumberRef, kCFNumberSInt32Type},
-    runloop::{CFRunLoopAddSource, CFRunLoopGetCurrent, CFRunLoopRun, kCFRunLoopDefaultMode},
+    runloop::{
+        CFRunLoopAddSource, CFRunLoopGetCurrent, CFRunLoopRef, CFRunLoopRun, CFRunLoopRunInMode,
+        CFRunLoopStop, kCFRunLoopDefaultMode,
+    },
     string::{
         CFStringCreateWithCString, CFStringGetCString, CFStringGetLength, CFStringRef,
         kCFStringEncodingUTF8,
@@ -19,17 +25,17 @@ use core_foundation::{
 };
 use crossbeam_channel::Sender;
 use io_kit_sys::{
-    IOIteratorNext, IONotificationPortCreate, IONotificationPortGetRunLoopSource,
-    IONotificationPortRef, IOObjectRelease, IORegistryEntryCreateCFProperty,
-    IORegistryEntryCreateIterator, IORegistryEntryGetPath, IOServiceAddMatchingNotification,
-    IOServiceGetMatchingServices, IOServiceMatching, kIOMasterPortDefault,
-    kIORegistryIterateRecursively,
+    IOIteratorNext, IONotificationPortCreate, IONotificationPortDestroy,
+    IONotificationPortGetRunLoopSource, IONotificationPortRef, IOObjectRelease,
+    IORegistryEntryCreateCFProperty, IORegistryEntryCreateIterator, IORegistryEntryGetPath,
+    IOServiceAddMatchingNotification, IOServiceGetMatchingServices, IOServiceMatching,
+    kIOMasterPortDefault, kIORegistryIterateRecursively,
     keys::kIOPublishNotification,
     types::{io_iterator_t, io_service_t},
 };
-use log::info;
+use log::{info, warn};
 
-use crate::{DeviceEvent, DeviceMonitor, RawDeviceInfo};
+use crate::{DeviceEvent, DeviceMonitor, MonitorError, MonitorHandle, RawDeviceInfo};
 
 const IO_USB_DEVICE: &str = "IOUSBDevice";
 const IO_SERVICE: &str = "IOService";
@@ -40,7 +46,10 @@ const IO_SERVICE_TERMINATE: &str = "IOServiceTerminate";
 const ID_VENDOR: &str = "idVendor";
 const ID_PRODUCT: &str = "idProduct";
 const USB_SERIAL_NUMBER: &str = "USB Serial Number";
-// const USB_PRODUCT_NAME: &str = "USB Product Name";
+const USB_PRODUCT_NAME: &str = "USB Product Name";
+const USB_VENDOR_NAME: &str = "USB Vendor Name";
+const BCD_DEVICE: &str = "bcdDevice";
+const B_DEVICE_CLASS: &str = "bDeviceClass";
 const LOCATION_ID: &str = "locationID";
 
 // 建立与内核的通信管道
@@ -372,12 +381,17 @@ impl MacMonitor {
             port_path,
             system_path: registry_path,
             system_path_alt: usable_path,
+            manufacturer: Self::get_ioreg_string(service, USB_VENDOR_NAME),
+            product: Self::get_ioreg_string(service, USB_PRODUCT_NAME),
+            bcd_device: Self::get_ioreg_number(service, BCD_DEVICE).map(|v| v as u16),
+            device_class: Self::get_ioreg_number(service, B_DEVICE_CLASS).map(|v| v as u8),
+            ..Default::default()
         })
     }
 }
 
 impl DeviceMonitor for MacMonitor {
-    fn start(&self, tx: Sender<DeviceEvent>) -> Result<()> {
+    fn start(&self, tx: Sender<DeviceEvent>) -> Result<MonitorHandle> {
         // Prepare the context
         // We need to perform a scan_now first to populate active_paths with currently existing devices,
         // to avoid receiving duplicate Attach events immediately after startup.
@@ -387,6 +401,7 @@ impl DeviceMonitor for MacMonitor {
         let context = Box::new(MonitorContext {
             tx,
             activate_paths: Mutex::new(HashSet::new()),
+            closed: AtomicBool::new(false),
         });
 
         // Pointer Magic
@@ -405,7 +420,7 @@ impl DeviceMonitor for MacMonitor {
 
         // Start the background thread.
         // 启动后台线程
-        thread::spawn(move || {
+        let handle = MonitorHandle::spawn("iokit-monitor", move |stop| {
             let ctx_ptr = context_addr as *mut c_void;
 
             info!("[macOS] Start the IOKit listening thread.");
@@ -426,7 +441,7 @@ impl DeviceMonitor for MacMonitor {
             // Publish
             // 注册“插入”
             let mut iter_add: io_iterator_t = 0;
-            unsafe {
+            let ret_add = unsafe {
                 IOServiceAddMatchingNotification(
                     notify_port,
                     kIOPublishNotification as *mut i8,
@@ -444,7 +459,7 @@ impl DeviceMonitor for MacMonitor {
             // 注册“拔出”
             let mut iter_rem: io_iterator_t = 0;
             let term_key = CString::new(IO_SERVICE_TERMINATE).unwrap();
-            unsafe {
+            let ret_rem = unsafe {
                 IOServiceAddMatchingNotification(
                     notify_port,
                     term_key.as_ptr() as *mut i8,
@@ -456,12 +471,52 @@ impl DeviceMonitor for MacMonitor {
             };
             unsafe { device_added_callback(ctx_ptr, iter_rem) };
 
-            // This function initiates an infinite loop, blocking the current thread until the program exits.
-            // 启动死循环,这个函数会阻塞当前线程，直到程序退出
-            unsafe { CFRunLoopRun() };
-        });
+            // Let a stop request interrupt the run loop from another thread
+            // 允许其它线程通过停止请求打断 run loop
+            let waker = RunLoopWaker::new(run_loop);
+            stop.on_stop(move || waker.stop());
+
+            // Without both notifications hot-plug events can't be seen
+            // 两个通知没有都注册成功时, 无法感知热插拔
+            let ctx = unsafe { &*(ctx_ptr as *const MonitorContext) };
+            if ret_add != 0 || ret_rem != 0 {
+                let error = MonitorError::Socket(format!(
+                    "IOServiceAddMatchingNotification failed with code: {}",
+                    if ret_add != 0 { ret_add } else { ret_rem }
+                ));
+                warn!("[macOS] {}", error);
+                stop.report_error(error.clone());
+                ctx.tx.send(DeviceEvent::MonitorError(error)).ok();
+            }
+
+            // Run in slices: a CFRunLoopStop issued just before the loop starts would be lost
+            // 分段运行: 在 run loop 开始运行之前发出的 CFRunLoopStop 会丢失
+            while !stop.is_stopped() {
+                if ctx.closed.load(Ordering::Relaxed) {
+                    stop.report_error(MonitorError::ChannelClosed);
+                    break;
+                }
+                unsafe { CFRunLoopRunInMode(kCFRunLoopDefaultMode, 1.0, 0) };
+            }
 
-        Ok(())
+            // Release everything the thread owns, including the context
+            // 释放线程持有的所有资源, 包括上下文
+            unsafe {
+                IOObjectRelease(iter_add);
+                IOObjectRelease(iter_rem);
+                IONotificationPortDestroy(notify_port);
+                drop(Box::from_raw(ctx_ptr as *mut MonitorContext));
+            }
+
+            info!("[macOS] The IOKit listening thread has been stopped.");
+        })
+        .inspect_err(|_| {
+            // The thread never ran, take the context back
+            // 线程没有启动, 收回上下文
+            unsafe { drop(Box::from_raw(context_addr as *mut MonitorContext)) };
+        })?;
+
+        Ok(handle)
     }
 
     fn scan_now(&self) -> Result<Vec<RawDeviceInfo>> {
@@ -527,12 +582,16 @@ unsafe extern "C" fn device_added_callback(ref_con: *mut c_void, iterator: io_it
         if activate_paths.contains(&registry_path) {
             activate_paths.remove(&registry_path);
 
-            ctx.tx.send(DeviceEvent::Detached(registry_path)).ok();
+            if ctx.tx.send(DeviceEvent::Detached(registry_path)).is_err() {
+                ctx.closed.store(true, Ordering::Relaxed);
+            }
         } else {
             if let Some(dev) = MacMonitor::parse_device(service) {
                 activate_paths.insert(registry_path);
 
-                ctx.tx.send(DeviceEvent::Attached(dev)).ok();
+                if ctx.tx.send(DeviceEvent::Attached(dev)).is_err() {
+                    ctx.closed.store(true, Ordering::Relaxed);
+                }
             }
         }
 
@@ -554,7 +613,34 @@ unsafe extern "C" fn device_added_callback(ref_con: *mut c_void, iterator: io_it
     }
 }
 
+// A retained run loop that other threads may stop
+// 持有引用的 run loop, 其它线程可以通过它停止循环
+struct RunLoopWaker(CFRunLoopRef);
+
+// CFRunLoopStop is documented as thread-safe
+// CFRunLoopStop 可以在任意线程调用
+unsafe impl Send for RunLoopWaker {}
+unsafe impl Sync for RunLoopWaker {}
+
+impl RunLoopWaker {
+    fn new(run_loop: CFRunLoopRef) -> Self {
+        unsafe { CFRetain(run_loop as CFTypeRef) };
+        Self(run_loop)
+    }
+
+    fn stop(&self) {
+        unsafe { CFRunLoopStop(self.0) };
+    }
+}
+
+impl Drop for RunLoopWaker {
+    fn drop(&mut self) {
+        unsafe { CFRelease(self.0 as CFTypeRef) };
+    }
+}
+
 struct MonitorContext {
     tx: Sender<DeviceEvent>,                // Send communication。 发送通信
     activate_paths: Mutex<HashSet<String>>, // Notepad, recording the current device's Registry Path。 记事本，记录当前设备的 Registry Path
+    closed: AtomicBool,                     // Set once the receiver is gone。 接收端已关闭
 }
@@ -1,6 +1,9 @@
 #[cfg(target_os = "linux")]
 pub mod linux;
 
+#[cfg(target_os = "linux")]
+pub mod sysfs;
+
 #[cfg(target_os = "windows")]
 pub mod windows;
 
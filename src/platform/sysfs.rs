@@ -0,0 +1,239 @@
+// Reading devices straight from sysfs, with an optional root directory
+// 直接从 sysfs 读取设备, 可以指定根目录
+//
+// `LinuxMonitor` reads devices through libudev, which always looks at the live system.
+// `SysfsEnumerator` reads the same information from the files udev itself reads: the device
+// directories under `<root>/sys/devices` (attributes, `uevent`, the `subsystem` and `driver`
+// links) and udev's database under `<root>/run/udev/data` (ID_PATH, ID_SERIAL_SHORT, the
+// by-id / by-path links ...). Both go through the same `SysDevice` trait and the same parsing
+// code, so pointing the enumerator at a fixture tree tests the parsing without any hardware.
+// `LinuxMonitor` 通过 libudev 读取设备, 而 libudev 总是读取当前系统。`SysfsEnumerator` 从 udev
+// 自己读取的文件中获取同样的信息: `<root>/sys/devices` 下的设备目录 (属性、`uevent`、`subsystem`
+// 与 `driver` 链接) 以及 `<root>/run/udev/data` 下的 udev 数据库 (ID_PATH、ID_SERIAL_SHORT、
+// by-id / by-path 链接 ...)。两者共用 `SysDevice` trait 与同一套解析代码, 因此把枚举器指向一棵
+// 测试用的目录树即可在没有硬件的情况下测试解析逻辑。
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use super::linux::LinuxMonitor;
+use crate::RawDeviceInfo;
+
+/// 解析设备所需的最小接口, 由 libudev 的 `Device` 与 `SysfsDevice` 实现
+pub(crate) trait SysDevice: Sized {
+    /// /sys/devices/... 形式的路径
+    fn syspath(&self) -> Option<String>;
+    fn subsystem(&self) -> Option<String>;
+    fn devtype(&self) -> Option<String>;
+    /// /dev 下的设备节点
+    fn devnode(&self) -> Option<String>;
+    fn driver(&self) -> Option<String>;
+    /// sysfs 属性 (去掉结尾的换行)
+    fn attribute(&self, name: &str) -> Option<String>;
+    /// udev 属性 (uevent 与 udev 数据库)
+    fn property(&self, name: &str) -> Option<String>;
+    /// 最近的指定子系统与类型的祖先设备
+    fn parent_with(&self, subsystem: &str, devtype: &str) -> Option<Self>;
+    /// 所有后代设备 (不含自身)
+    fn descendants(&self) -> Vec<Self>;
+}
+
+/// sysfs 目录树中的一个设备
+#[derive(Debug, Clone)]
+pub(crate) struct SysfsDevice {
+    root: PathBuf,
+    dir: PathBuf,
+    properties: HashMap<String, String>,
+}
+
+impl SysfsDevice {
+    // A directory is a device if it has a uevent file
+    // 带有 uevent 文件的目录才是设备
+    fn open(root: &Path, dir: PathBuf) -> Option<Self> {
+        let uevent = fs::read_to_string(dir.join("uevent")).ok()?;
+        let mut device = Self {
+            root: root.to_path_buf(),
+            dir,
+            properties: parse_properties(&uevent),
+        };
+
+        // udev keeps its own properties and the node's symlinks in a database file
+        // udev 把自己的属性与节点的符号链接保存在数据库文件中
+        if let Ok(data) = fs::read_to_string(root.join("run/udev/data").join(device.db_key()?)) {
+            let mut links = vec![];
+            for line in data.lines() {
+                if let Some(property) = line.strip_prefix("E:") {
+                    device.properties.extend(parse_properties(property));
+                } else if let Some(link) = line.strip_prefix("S:") {
+                    links.push(format!("/dev/{}", link));
+                }
+            }
+            if !links.is_empty() {
+                device
+                    .properties
+                    .insert("DEVLINKS".to_string(), links.join(" "));
+            }
+        }
+        Some(device)
+    }
+
+    // c<major>:<minor> for device nodes, +<subsystem>:<sysname> for everything else
+    // 有设备节点的为 c<major>:<minor>, 其余为 +<subsystem>:<sysname>
+    fn db_key(&self) -> Option<String> {
+        let subsystem = self.subsystem()?;
+        match (self.properties.get("MAJOR"), self.properties.get("MINOR")) {
+            (Some(major), Some(minor)) => {
+                let kind = if subsystem == "block" { 'b' } else { 'c' };
+                Some(format!("{}{}:{}", kind, major, minor))
+            }
+            _ => Some(format!(
+                "+{}:{}",
+                subsystem,
+                self.dir.file_name()?.to_str()?
+            )),
+        }
+    }
+
+    fn link_name(&self, name: &str) -> Option<String> {
+        let target = fs::read_link(self.dir.join(name)).ok()?;
+        Some(target.file_name()?.to_str()?.to_string())
+    }
+}
+
+impl SysDevice for SysfsDevice {
+    fn syspath(&self) -> Option<String> {
+        let relative = self.dir.strip_prefix(&self.root).ok()?;
+        Some(Path::new("/").join(relative).to_str()?.to_string())
+    }
+
+    fn subsystem(&self) -> Option<String> {
+        self.link_name("subsystem")
+    }
+
+    fn devtype(&self) -> Option<String> {
+        self.property("DEVTYPE")
+    }
+
+    fn devnode(&self) -> Option<String> {
+        self.property("DEVNAME")
+            .map(|name| format!("/dev/{}", name))
+    }
+
+    fn driver(&self) -> Option<String> {
+        self.link_name("driver").or_else(|| self.property("DRIVER"))
+    }
+
+    fn attribute(&self, name: &str) -> Option<String> {
+        let path = self.dir.join(name);
+        if !path.is_file() {
+            return None;
+        }
+        let value = fs::read_to_string(path).ok()?;
+        Some(value.trim_end_matches('\n').to_string())
+    }
+
+    fn property(&self, name: &str) -> Option<String> {
+        self.properties.get(name).cloned()
+    }
+
+    fn parent_with(&self, subsystem: &str, devtype: &str) -> Option<Self> {
+        let devices = self.root.join("sys/devices");
+        self.dir
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&devices))
+            .filter_map(|dir| Self::open(&self.root, dir.to_path_buf()))
+            .find(|parent| {
+                parent.subsystem().as_deref() == Some(subsystem)
+                    && parent.devtype().as_deref() == Some(devtype)
+            })
+    }
+
+    fn descendants(&self) -> Vec<Self> {
+        let mut dirs = vec![];
+        device_dirs(&self.dir, &mut dirs);
+        dirs.into_iter()
+            .filter_map(|dir| Self::open(&self.root, dir))
+            .collect()
+    }
+}
+
+// KEY=VALUE lines, as found in uevent files and udev's E: records
+// KEY=VALUE 形式的行, 见于 uevent 文件与 udev 数据库的 E: 记录
+fn parse_properties(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+// Every device directory below `dir`, without following the subsystem / driver links
+// `dir` 下的所有设备目录, 不跟随 subsystem / driver 等链接
+fn device_dirs(dir: &Path, out: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut subdirs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.path())
+        .collect();
+    subdirs.sort();
+
+    for subdir in subdirs {
+        if subdir.join("uevent").is_file() {
+            out.push(subdir.clone());
+        }
+        device_dirs(&subdir, out);
+    }
+}
+
+/// 基于 sysfs 的设备枚举器, 可以指向真实系统之外的根目录 (例如测试用的目录树)
+#[derive(Debug, Clone)]
+pub struct SysfsEnumerator {
+    root: PathBuf,
+}
+
+impl Default for SysfsEnumerator {
+    fn default() -> Self {
+        Self::new("/")
+    }
+}
+
+impl SysfsEnumerator {
+    /// `root` 下应包含 `sys/devices`, 可选包含 `run/udev/data`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// 根目录
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 枚举所有 USB 设备 (与 `LinuxMonitor::scan_now` 的结果一致), 按 system_path 排序
+    pub fn scan(&self) -> Result<Vec<RawDeviceInfo>> {
+        let devices = self.root.join("sys/devices");
+        fs::read_dir(&devices).with_context(|| format!("failed to read {}", devices.display()))?;
+
+        let mut dirs = vec![];
+        device_dirs(&devices, &mut dirs);
+        Ok(dirs
+            .into_iter()
+            .filter_map(|dir| SysfsDevice::open(&self.root, dir))
+            .filter_map(|dev| LinuxMonitor::parse_device(&dev))
+            .collect())
+    }
+
+    /// 读取单个设备, `system_path` 为 /sys/devices/... 形式 (不含根目录)
+    pub fn device(&self, system_path: &str) -> Option<RawDeviceInfo> {
+        let dir = self.root.join(system_path.trim_start_matches('/'));
+        LinuxMonitor::parse_device(&SysfsDevice::open(&self.root, dir)?)
+    }
+}
@@ -1,7 +1,8 @@
-use std::{collections::HashSet, thread, time::Duration};
+use std::{collections::HashSet, time::Duration};
 
 use anyhow::Result;
 use crossbeam_channel::Sender;
+use log::warn;
 use windows::Win32::Devices::DeviceAndDriverInstallation::{
     DIGCF_ALLCLASSES, DIGCF_PRESENT, HDEVINFO, SP_DEVINFO_DATA, SPDRP_FRIENDLYNAME,
     SPDRP_HARDWAREID, SPDRP_LOCATION_INFORMATION, SetupDiDestroyDeviceInfoList,
@@ -9,7 +10,7 @@ use windows::Win32::Devices::DeviceAndDriverInstallation::{
     SetupDiGetDeviceRegistryPropertyW,
 };
 
-use crate::{DeviceEvent, DeviceMonitor, RawDeviceInfo};
+use crate::{DeviceEvent, DeviceMonitor, MonitorError, MonitorHandle, RawDeviceInfo};
 
 pub struct WindowsMonitor;
 
@@ -221,6 +222,7 @@ impl WindowsMonitor {
                 port_path,
                 system_path,
                 system_path_alt,
+                ..Default::default()
             });
         }
 
@@ -234,31 +236,55 @@ impl WindowsMonitor {
 }
 
 impl DeviceMonitor for WindowsMonitor {
-    fn start(&self, tx: Sender<DeviceEvent>) -> Result<()> {
+    fn start(&self, tx: Sender<DeviceEvent>) -> Result<MonitorHandle> {
         // 1. 初始扫描 (补发存量)
         let mut known_devices: HashSet<String> = HashSet::new();
 
-        if let Ok(devices) = self.scan_now() {
-            for dev in devices {
-                known_devices.insert(dev.system_path.clone());
-                tx.send(DeviceEvent::Attached(dev)).ok();
+        match self.scan_now() {
+            Ok(devices) => {
+                for dev in devices {
+                    known_devices.insert(dev.system_path.clone());
+                    tx.send(DeviceEvent::Attached(dev)).ok();
+                }
+            }
+            Err(e) => {
+                let error = MonitorError::Enumeration(format!("{:#}", e));
+                warn!("[Windows] {}", error);
+                tx.send(DeviceEvent::MonitorError(error)).ok();
             }
         }
 
         // 2. 启动轮询线程
-        thread::spawn(move || {
-            loop {
-                // 1秒轮询一次
-                thread::sleep(Duration::from_secs(1));
-
+        let handle = MonitorHandle::spawn("setupapi-monitor", move |stop| {
+            // 扫描失败期间只上报一次错误, 恢复后发送 MonitorRecovered
+            let mut failing = false;
+            // 1秒轮询一次, 等待期间收到停止请求会立即退出
+            while !stop.wait_timeout(Duration::from_secs(1)) {
                 // 再次扫描
                 let current_devices = match Self::scan_now() {
                     Ok(devs) => devs,
                     Err(e) => {
-                        eprintln!("[Windows] Scan failed: {:?}", e);
+                        if !failing {
+                            let error = MonitorError::Enumeration(format!("{:#}", e));
+                            warn!("[Windows] {}", error);
+                            stop.report_error(error.clone());
+                            if tx.send(DeviceEvent::MonitorError(error)).is_err() {
+                                stop.report_error(MonitorError::ChannelClosed);
+                                return;
+                            }
+                            failing = true;
+                        }
                         continue;
                     }
                 };
+                if failing {
+                    failing = false;
+                    stop.report_ok();
+                    if tx.send(DeviceEvent::MonitorRecovered).is_err() {
+                        stop.report_error(MonitorError::ChannelClosed);
+                        return;
+                    }
+                }
 
                 // 找出现在的 ID 集合
                 let current_ids: HashSet<String> = current_devices
@@ -267,9 +293,10 @@ impl DeviceMonitor for WindowsMonitor {
                     .collect();
 
                 // Check A: 新增的设备 (Present now but not before)
+                let mut closed = false;
                 for dev in &current_devices {
                     if !known_devices.contains(&dev.system_path) {
-                        tx.send(DeviceEvent::Attached(dev.clone())).ok();
+                        closed |= tx.send(DeviceEvent::Attached(dev.clone())).is_err();
                         known_devices.insert(dev.system_path.clone());
                     }
                 }
@@ -279,15 +306,21 @@ impl DeviceMonitor for WindowsMonitor {
                 // 我们利用这个副作用来发送移除事件
                 known_devices.retain(|old_path| {
                     if !current_ids.contains(old_path) {
-                        tx.send(DeviceEvent::Detached(old_path.clone())).ok();
+                        closed |= tx.send(DeviceEvent::Detached(old_path.clone())).is_err();
                         return false; // 从 known_devices 删除
                     }
                     true // 保留
                 });
+
+                // 接收端已被 drop, 线程没有继续运行的意义
+                if closed {
+                    stop.report_error(MonitorError::ChannelClosed);
+                    return;
+                }
             }
-        });
+        })?;
 
-        Ok(())
+        Ok(handle)
     }
 
     fn scan_now(&self) -> Result<Vec<RawDeviceInfo>> {
@@ -0,0 +1,333 @@
+// Recording and replay of device event sessions
+// 设备事件会话的录制与回放
+//
+// `RecordingMonitor` wraps any `DeviceMonitor` and writes every event it emits to a JSON Lines
+// file, one `RecordedEvent` per line with the time since the recording started and the wall
+// clock time. `ReplayMonitor` plays such a file back through the same `start(tx)` interface,
+// at the original pace or faster, so a hotplug sequence seen in the field can be reproduced
+// on a desk (or in a test) without the hardware.
+// `RecordingMonitor` 包装任意 `DeviceMonitor`, 把它发出的每个事件写入 JSON Lines 文件,
+// 每行一个 `RecordedEvent`, 包含距录制开始的时间与系统时间。`ReplayMonitor` 通过同样的
+// `start(tx)` 接口回放该文件, 可以按原速或加速播放, 因此现场出现的热插拔序列可以在没有硬件的
+// 情况下 (或在测试中) 复现。
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result, bail};
+use crossbeam_channel::Sender;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    DeviceEvent, DeviceMonitor, MonitorError, MonitorHandle, MonitorHealth, RawDeviceInfo,
+};
+
+/// 录制文件中的一行
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// 距录制开始的毫秒数 (回放时按它安排节奏)
+    pub elapsed_ms: u64,
+    /// 事件发生时的系统时间 (Unix 毫秒), 便于与日志对照
+    pub unix_ms: u64,
+    pub event: DeviceEvent,
+}
+
+/// 录制监听器: 转发并记录被包装监听器的全部事件
+pub struct RecordingMonitor {
+    inner: Box<dyn DeviceMonitor>,
+    path: PathBuf,
+}
+
+impl RecordingMonitor {
+    /// 包装 `inner`, 每次 `start` 都会重新创建 `path` 并从头录制
+    pub fn new(inner: Box<dyn DeviceMonitor>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            path: path.into(),
+        }
+    }
+
+    /// 录制文件的路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl DeviceMonitor for RecordingMonitor {
+    fn start(&self, tx: Sender<DeviceEvent>) -> Result<MonitorHandle> {
+        let file = File::create(&self.path)
+            .with_context(|| format!("failed to create {}", self.path.display()))?;
+        let path = self.path.clone();
+
+        let (inner_tx, inner_rx) = crossbeam_channel::unbounded();
+        let inner = self.inner.start(inner_tx)?;
+
+        let handle = MonitorHandle::spawn("recording-monitor", move |stop| {
+            info!("[Recorder] Recording device events to {}", path.display());
+
+            // Stopping the recorder drops `inner`, which stops the wrapped monitor too
+            // 录制线程退出时会 drop `inner`, 被包装的监听器随之停止
+            let inner = inner;
+            let (stop_tx, stop_rx) = crossbeam_channel::bounded(1);
+            stop.on_stop(move || {
+                let _ = stop_tx.try_send(());
+            });
+
+            let mut writer = BufWriter::new(file);
+            let mut write_failed = false;
+            let started = Instant::now();
+
+            loop {
+                let event = crossbeam_channel::select! {
+                    recv(stop_rx) -> _ => return,
+                    recv(inner_rx) -> event => match event {
+                        Ok(event) => event,
+                        // The wrapped monitor thread has exited
+                        // 被包装的监听线程已退出
+                        Err(_) => {
+                            if let MonitorHealth::Stopped(Some(error)) = inner.health() {
+                                stop.report_error(error);
+                            }
+                            return;
+                        }
+                    },
+                };
+
+                match &event {
+                    DeviceEvent::MonitorError(error) => stop.report_error(error.clone()),
+                    DeviceEvent::MonitorRecovered => stop.report_ok(),
+                    _ => {}
+                }
+
+                let record = RecordedEvent {
+                    elapsed_ms: started.elapsed().as_millis() as u64,
+                    unix_ms: unix_ms(),
+                    event,
+                };
+                // Flushed per line so that a crash keeps everything up to the last event;
+                // a full disk must not take the application's events down with it
+                // 每行都 flush, 崩溃时也能保留到最后一个事件; 磁盘写满不应影响应用收到事件
+                if let Err(e) = write_record(&mut writer, &record) {
+                    if !write_failed {
+                        warn!("[Recorder] Failed to write {}: {}", path.display(), e);
+                    }
+                    write_failed = true;
+                }
+
+                if tx.send(record.event).is_err() {
+                    stop.report_error(MonitorError::ChannelClosed);
+                    return;
+                }
+            }
+        })?;
+
+        Ok(handle)
+    }
+
+    fn scan_now(&self) -> Result<Vec<RawDeviceInfo>> {
+        self.inner.scan_now()
+    }
+}
+
+fn write_record(writer: &mut impl Write, record: &RecordedEvent) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// 回放监听器: 按录制时的节奏 (可加速) 重新发出录制的事件
+#[derive(Clone)]
+pub struct ReplayMonitor {
+    events: Vec<RecordedEvent>,
+    speed: f64,
+    // Devices present at the current playback position, for `scan_now`
+    // 当前回放位置上在线的设备, 用于 `scan_now`
+    present: Arc<Mutex<BTreeMap<String, RawDeviceInfo>>>,
+}
+
+impl ReplayMonitor {
+    /// 回放给定的事件 (按原速)
+    pub fn new(events: Vec<RecordedEvent>) -> Self {
+        Self {
+            events,
+            speed: 1.0,
+            present: Arc::default(),
+        }
+    }
+
+    /// 读取 `RecordingMonitor` 写入的录制文件 (空行会被跳过)
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        let mut events = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = serde_json::from_str(line)
+                .with_context(|| format!("{}:{}", path.display(), index + 1))?;
+            events.push(event);
+        }
+        Ok(Self::new(events))
+    }
+
+    /// 设置回放速度: 1.0 为原速, 10.0 为十倍速; 必须是有限的正数
+    pub fn with_speed(mut self, speed: f64) -> Result<Self> {
+        // NaN fails the comparison as well
+        // NaN 同样无法通过该比较
+        if !(speed.is_finite() && speed > 0.0) {
+            bail!(
+                "replay speed must be a finite positive number, got {}",
+                speed
+            );
+        }
+        self.speed = speed;
+        Ok(self)
+    }
+
+    /// 将要回放的事件
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events
+    }
+}
+
+impl DeviceMonitor for ReplayMonitor {
+    fn start(&self, tx: Sender<DeviceEvent>) -> Result<MonitorHandle> {
+        let events = self.events.clone();
+        let speed = self.speed;
+        let present = Arc::clone(&self.present);
+        present.lock().unwrap().clear();
+
+        let handle = MonitorHandle::spawn("replay-monitor", move |stop| {
+            let started = Instant::now();
+            for record in &events {
+                let due = Duration::try_from_secs_f64(record.elapsed_ms as f64 / 1000.0 / speed)
+                    .unwrap_or(Duration::MAX);
+                let stopped = match due.checked_sub(started.elapsed()) {
+                    Some(wait) => stop.wait_timeout(wait),
+                    None => stop.is_stopped(),
+                };
+                if stopped {
+                    return;
+                }
+
+                match &record.event {
+                    DeviceEvent::Attached(dev) | DeviceEvent::Updated(dev) => {
+                        present
+                            .lock()
+                            .unwrap()
+                            .insert(dev.system_path.clone(), dev.clone());
+                    }
+                    DeviceEvent::Detached(path) => {
+                        present.lock().unwrap().remove(path);
+                    }
+                    DeviceEvent::MonitorError(error) => stop.report_error(error.clone()),
+                    DeviceEvent::MonitorRecovered => stop.report_ok(),
+                }
+
+                if tx.send(record.event.clone()).is_err() {
+                    stop.report_error(MonitorError::ChannelClosed);
+                    return;
+                }
+            }
+
+            // Like a real monitor, the handle keeps running until it is stopped
+            // 与真实监听器一样, 句柄在被停止之前一直保持运行
+            info!(
+                "[Replay] All {} recorded events have been replayed.",
+                events.len()
+            );
+            while !stop.wait_timeout(Duration::from_secs(1)) {}
+        })?;
+
+        Ok(handle)
+    }
+
+    fn scan_now(&self) -> Result<Vec<RawDeviceInfo>> {
+        Ok(self.present.lock().unwrap().values().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimulatedMonitor, test_support::device};
+
+    #[test]
+    fn recorded_session_replays_the_same_events() {
+        let path = std::env::temp_dir().join(format!("usb-resolver-{}.jsonl", std::process::id()));
+
+        let simulated = SimulatedMonitor::new();
+        simulated.plug(device("A", "1-1"));
+        let recorder = RecordingMonitor::new(Box::new(simulated.clone()), &path);
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let handle = recorder.start(tx).unwrap();
+
+        rx.recv().unwrap();
+        simulated.plug(device("B", "1-2"));
+        simulated.fail(MonitorError::Socket("hub reset".to_string()));
+        simulated.unplug("/sys/devices/1-1");
+        simulated.recover();
+        let live: Vec<DeviceEvent> = rx.iter().take(4).collect();
+        drop(handle);
+
+        let replay = ReplayMonitor::load(&path).unwrap().with_speed(1e6).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replay.events().len(), 5);
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let _handle = replay.start(tx).unwrap();
+        let replayed: Vec<DeviceEvent> = rx.iter().take(5).collect();
+        assert_eq!(replayed[0], DeviceEvent::Attached(device("A", "1-1")));
+        assert_eq!(replayed[1..], live[..]);
+        assert_eq!(replay.scan_now().unwrap(), [device("B", "1-2")]);
+    }
+
+    #[test]
+    fn replay_speed_scales_the_delays() {
+        let events = (0..3)
+            .map(|i| RecordedEvent {
+                elapsed_ms: i * 200,
+                unix_ms: 0,
+                event: DeviceEvent::MonitorRecovered,
+            })
+            .collect();
+        let replay = ReplayMonitor::new(events).with_speed(10.0).unwrap();
+
+        let started = Instant::now();
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let _handle = replay.start(tx).unwrap();
+        assert_eq!(rx.iter().take(3).count(), 3);
+
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(40), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn replay_speed_must_be_finite_and_positive() {
+        for speed in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(
+                ReplayMonitor::new(vec![]).with_speed(speed).is_err(),
+                "{}",
+                speed
+            );
+        }
+    }
+}
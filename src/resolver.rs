@@ -0,0 +1,734 @@
+// Role resolver: turns raw hot-plug events into role-level events
+// 角色解析器: 把底层的热插拔事件转换为角色级别的事件
+//
+// The platform monitors only know about "a device appeared / disappeared".
+// The resolver owns the rule table and the role <-> device binding table, so consumers
+// no longer need to re-run `DeviceRule::matches` themselves.
+// 平台监听器只知道 "某个设备出现/消失"。
+// 解析器持有规则表以及 角色 <-> 设备 的绑定表, 业务层无需再手动调用 `DeviceRule::matches`。
+//
+// The rule table can be replaced while running (`reload` / `watch_rules`). All present
+// devices are then re-evaluated against the new rules and only the differences are emitted.
+// 规则表可以在运行时替换 (`reload` / `watch_rules`)。替换后所有在线设备会按新规则重新分配,
+// 只发出发生变化的部分。
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use anyhow::{Result, bail};
+use crossbeam_channel::{Receiver, Sender};
+use log::{info, warn};
+
+use crate::{
+    ConfigDiagnostic, DeviceEvent, DeviceMonitor, DeviceRule, MonitorError, MonitorHandle,
+    MonitorHealth, RawDeviceInfo, ResolvedDevice, RoleId,
+    assign::{Rejection, assign},
+    get_monitor, validate_rules,
+    watch::RulesWatcher,
+};
+
+/// 角色级事件
+// Events are rare and short-lived, boxing the device info would only make matching clumsier
+// 事件数量很少且生命周期很短, 把设备信息装箱只会让模式匹配更麻烦
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum RoleEvent {
+    /// 某个角色绑定到了一个设备
+    RoleAttached(ResolvedDevice),
+    /// 某个角色失去了它绑定的设备
+    RoleDetached {
+        role: RoleId,
+        last_device: RawDeviceInfo,
+    },
+    /// 角色绑定的设备没有变, 但设备信息更新了 (例如 tty 节点刚刚出现)
+    RoleUpdated(ResolvedDevice),
+    /// 某个角色从一个设备换绑到了另一个设备 (例如规则被修改后)
+    /// 使用方应先释放 `previous`, 再打开 `current`
+    RoleRebound {
+        role: RoleId,
+        previous: RawDeviceInfo,
+        current: ResolvedDevice,
+    },
+    /// 某个角色同时被多个设备以相同强度满足 (例如只配置了 VID/PID 的规则)
+    /// 候选集合变化时才会再次发出
+    Ambiguous {
+        role: RoleId,
+        candidates: Vec<RawDeviceInfo>,
+        /// 规则配置了 `on_ambiguous: refuse`, 因而没有绑定
+        refused: bool,
+    },
+    /// 新的规则集已生效 (之后紧跟着由此产生的角色变化)
+    RulesReloaded { rule_count: usize },
+    /// 新的规则文件未通过校验, 继续使用旧规则
+    RulesRejected { diagnostics: Vec<ConfigDiagnostic> },
+    /// 底层监听器出现故障 (见 `DeviceEvent::MonitorError`)
+    MonitorError(MonitorError),
+    /// 底层监听器已恢复, 断开期间的变化已经补发
+    MonitorRecovered,
+}
+
+// Requests from the resolver API (or the rules watcher) to the resolver thread
+// 解析器 API (或规则文件监听器) 发给解析线程的请求
+enum Control {
+    Rules(Vec<DeviceRule>),
+    Rejected(Vec<ConfigDiagnostic>),
+}
+
+/// 解析器内部状态: 当前在线的设备 + 角色绑定表
+#[derive(Debug, Default)]
+struct ResolverState {
+    rules: Vec<DeviceRule>,
+    // Key = system_path. BTreeMap keeps the iteration order stable.
+    // Key = system_path。使用 BTreeMap 保证遍历顺序稳定
+    devices: BTreeMap<String, RawDeviceInfo>,
+    bindings: HashMap<RoleId, ResolvedDevice>,
+    // Why the losing candidates lost, from the last reconcile
+    // 上一次分配中落选候选的原因
+    rejected: Vec<Rejection>,
+    // Ambiguous roles from the last reconcile -> candidate system_paths
+    // 上一次分配中存在歧义的角色 -> 候选设备的 system_path
+    ambiguous: HashMap<RoleId, Vec<String>>,
+}
+
+impl ResolverState {
+    fn new(rules: Vec<DeviceRule>) -> Self {
+        Self {
+            rules,
+            ..Default::default()
+        }
+    }
+
+    // Apply a raw event, return the resulting role events
+    // 处理一个底层事件, 返回由此产生的角色事件
+    fn handle(&mut self, event: DeviceEvent) -> Vec<RoleEvent> {
+        match event {
+            DeviceEvent::Attached(dev) => {
+                self.devices.insert(dev.system_path.clone(), dev);
+            }
+            DeviceEvent::Detached(path) => {
+                if self.devices.remove(&path).is_none() {
+                    return vec![];
+                }
+            }
+            DeviceEvent::Updated(dev) => return self.update(dev),
+            // Monitor health is passed through unchanged
+            // 监听器的健康状态原样转发
+            DeviceEvent::MonitorError(error) => return vec![RoleEvent::MonitorError(error)],
+            DeviceEvent::MonitorRecovered => return vec![RoleEvent::MonitorRecovered],
+        }
+
+        self.reconcile()
+    }
+
+    // New information about a present device: it may change the bindings (e.g. a driver
+    // filter now holds), otherwise a role that keeps the device is told about the update
+    // 在线设备的信息更新: 可能改变绑定关系 (例如驱动过滤条件现在成立了),
+    // 否则继续持有该设备的角色会收到更新通知
+    fn update(&mut self, dev: RawDeviceInfo) -> Vec<RoleEvent> {
+        let path = dev.system_path.clone();
+        match self.devices.get_mut(&path) {
+            Some(known) if *known != dev => *known = dev,
+            _ => return vec![],
+        }
+
+        let mut events = self.reconcile();
+        let mentioned: HashSet<RoleId> = events
+            .iter()
+            .filter_map(|e| match e {
+                RoleEvent::RoleAttached(new) | RoleEvent::RoleRebound { current: new, .. } => {
+                    Some(new.role.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        let mut updated: Vec<&ResolvedDevice> = self
+            .bindings
+            .values()
+            .filter(|b| b.device.system_path == path && !mentioned.contains(&b.role))
+            .collect();
+        updated.sort_by(|a, b| a.role.cmp(&b.role));
+        events.extend(updated.into_iter().cloned().map(RoleEvent::RoleUpdated));
+        events
+    }
+
+    // Forget devices and bindings, keep the rules
+    // 清空设备与绑定关系, 保留规则
+    fn reset(&mut self) {
+        *self = Self::new(std::mem::take(&mut self.rules));
+    }
+
+    // Swap in a new rule set and re-evaluate every present device
+    // 换上新的规则集, 并重新评估所有在线设备
+    fn set_rules(&mut self, rules: Vec<DeviceRule>) -> Vec<RoleEvent> {
+        let rule_count = rules.len();
+        self.rules = rules;
+
+        let mut events = vec![RoleEvent::RulesReloaded { rule_count }];
+        events.extend(self.reconcile());
+        events
+    }
+
+    // Compute the bindings from scratch, then diff them against the current table
+    // 从头计算绑定关系, 再与当前绑定表做差异比较
+    fn reconcile(&mut self) -> Vec<RoleEvent> {
+        let devices: Vec<RawDeviceInfo> = self.devices.values().cloned().collect();
+        let assignment = assign(&self.rules, &devices);
+        self.rejected = assignment.rejected;
+
+        let next: HashMap<RoleId, ResolvedDevice> = assignment
+            .bound
+            .iter()
+            .map(|b| (b.role.clone(), b.clone()))
+            .collect();
+        let mut events = vec![];
+
+        // A role that moves to a device no other role held before is reported as a single
+        // rebind. If the new device was bound to another role, it is detached + attached
+        // instead, so consumers never see one device bound to two roles.
+        // 角色换到一个之前没有被其它角色占用的设备时, 作为一次换绑上报。
+        // 如果新设备之前属于另一个角色, 则拆成 移除 + 绑定, 保证使用方不会看到一个设备同时属于两个角色。
+        let held: HashSet<&str> = self
+            .bindings
+            .values()
+            .map(|b| b.device.system_path.as_str())
+            .collect();
+        let rebound: HashSet<&str> = self
+            .bindings
+            .iter()
+            .filter(|(role, old)| {
+                next.get(*role).is_some_and(|new| {
+                    new.device.system_path != old.device.system_path
+                        && !held.contains(new.device.system_path.as_str())
+                })
+            })
+            .map(|(role, _)| role.as_str())
+            .collect();
+
+        // Detach first, so a device moving between roles is released before it is re-bound
+        // 先发移除事件, 这样设备在角色间迁移时会先释放再绑定
+        for (role, old) in &self.bindings {
+            let still_bound = next
+                .get(role)
+                .is_some_and(|new| new.device.system_path == old.device.system_path);
+            if !still_bound && !rebound.contains(role.as_str()) {
+                events.push(RoleEvent::RoleDetached {
+                    role: role.clone(),
+                    last_device: old.device.clone(),
+                });
+            }
+        }
+
+        // Report ambiguities that are new or whose candidate set changed
+        // 上报新出现的或候选集合发生变化的歧义
+        let mut ambiguous = HashMap::new();
+        for ambiguity in assignment.ambiguous {
+            let paths: Vec<String> = ambiguity
+                .candidates
+                .iter()
+                .map(|d| d.system_path.clone())
+                .collect();
+            if self.ambiguous.get(&ambiguity.role) != Some(&paths) {
+                warn!(
+                    "[Resolver] Role '{}' is ambiguous between {:?} (refused: {})",
+                    ambiguity.role, paths, ambiguity.refused
+                );
+                events.push(RoleEvent::Ambiguous {
+                    role: ambiguity.role.clone(),
+                    candidates: ambiguity.candidates,
+                    refused: ambiguity.refused,
+                });
+            }
+            ambiguous.insert(ambiguity.role, paths);
+        }
+        self.ambiguous = ambiguous;
+
+        // Rebinds release their old device, so they go before the plain attaches.
+        // `bound` is already in rule order.
+        // 换绑会释放旧设备, 因此排在普通绑定之前。`bound` 本身就是按规则顺序排列的。
+        let mut attached = vec![];
+        for new in assignment.bound {
+            match self.bindings.get(&new.role) {
+                Some(old) if old.device.system_path == new.device.system_path => {}
+                Some(old) if rebound.contains(new.role.as_str()) => {
+                    events.push(RoleEvent::RoleRebound {
+                        role: new.role.clone(),
+                        previous: old.device.clone(),
+                        current: new,
+                    });
+                }
+                _ => attached.push(RoleEvent::RoleAttached(new)),
+            }
+        }
+        events.extend(attached);
+
+        self.bindings = next;
+        events
+    }
+}
+
+/// 角色解析器: 持有规则与监听器, 维护 角色 <-> 设备 的绑定表
+pub struct Resolver {
+    monitor: Box<dyn DeviceMonitor>,
+    state: Arc<Mutex<ResolverState>>,
+    control_tx: Sender<Control>,
+    control_rx: Receiver<Control>,
+    watcher: Mutex<Option<RulesWatcher>>,
+    // Set while a resolver thread is running: runs share the state and the control channel
+    // 解析线程运行期间置位: 各次运行共用状态与控制 channel
+    running: Arc<AtomicBool>,
+}
+
+// Clears `Resolver::running` when the run ends, or when it fails to start
+// 运行结束 (或启动失败) 时清除 `Resolver::running`
+struct Running(Arc<AtomicBool>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Resolver {
+    /// 使用当前平台的监听器创建解析器
+    pub fn new(rules: Vec<DeviceRule>) -> Self {
+        Self::with_monitor(rules, get_monitor())
+    }
+
+    /// 使用指定的监听器创建解析器
+    pub fn with_monitor(rules: Vec<DeviceRule>, monitor: Box<dyn DeviceMonitor>) -> Self {
+        let (control_tx, control_rx) = crossbeam_channel::unbounded();
+        Self {
+            monitor,
+            state: Arc::new(Mutex::new(ResolverState::new(rules))),
+            control_tx,
+            control_rx,
+            watcher: Mutex::new(None),
+            running: Arc::default(),
+        }
+    }
+
+    /// 启动监听, 在后台线程中把设备事件翻译为角色事件并通过 channel 发送
+    /// 返回的句柄被 drop 时解析线程与底层监听器一起停止; 停止后可以再次 `start`
+    /// 上一次启动的句柄仍在运行时返回错误 (每个角色事件只会发给一个接收端)
+    pub fn start(&self, tx: Sender<RoleEvent>) -> Result<MonitorHandle> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            bail!("the resolver is already running, drop its handle before starting it again");
+        }
+        let running = Running(Arc::clone(&self.running));
+
+        // Every run starts from an empty device table, the monitor re-reports what is present
+        // 每次启动都从空的设备表开始, 监听器会重新上报当前在线的设备
+        self.state.lock().unwrap().reset();
+
+        let (dev_tx, dev_rx) = crossbeam_channel::unbounded();
+        let monitor = self.monitor.start(dev_tx)?;
+
+        let state = Arc::clone(&self.state);
+        let mut control_rx = self.control_rx.clone();
+        let handle = MonitorHandle::spawn("role-resolver", move |stop| {
+            info!("[Resolver] The role resolver thread has been started.");
+            let _running = running;
+
+            // Stopping the resolver thread drops `monitor`, which stops the device monitor too
+            // 解析线程退出时会 drop `monitor`, 底层监听器随之停止
+            let monitor = monitor;
+            let (stop_tx, stop_rx) = crossbeam_channel::bounded(1);
+            stop.on_stop(move || {
+                let _ = stop_tx.try_send(());
+            });
+
+            loop {
+                let role_events = crossbeam_channel::select! {
+                    recv(stop_rx) -> _ => return,
+                    recv(dev_rx) -> event => match event {
+                        Ok(event) => {
+                            // The resolver handle mirrors the health of the device monitor
+                            // 解析器句柄的健康状态与底层监听器保持一致
+                            match &event {
+                                DeviceEvent::MonitorError(error) => stop.report_error(error.clone()),
+                                DeviceEvent::MonitorRecovered => stop.report_ok(),
+                                _ => {}
+                            }
+                            state.lock().unwrap().handle(event)
+                        }
+                        // The device monitor thread has exited
+                        // 底层监听线程已退出
+                        Err(_) => {
+                            if let MonitorHealth::Stopped(Some(error)) = monitor.health() {
+                                stop.report_error(error);
+                            }
+                            return;
+                        }
+                    },
+                    recv(control_rx) -> control => match control {
+                        Ok(Control::Rules(rules)) => state.lock().unwrap().set_rules(rules),
+                        Ok(Control::Rejected(diagnostics)) => {
+                            vec![RoleEvent::RulesRejected { diagnostics }]
+                        }
+                        // The resolver was dropped, keep translating device events
+                        // 解析器已被 drop, 继续翻译设备事件
+                        Err(_) => {
+                            control_rx = crossbeam_channel::never();
+                            continue;
+                        }
+                    },
+                };
+
+                for role_event in role_events {
+                    if tx.send(role_event).is_err() {
+                        // The consumer is gone, nothing left to do
+                        // 接收端已关闭, 没必要继续
+                        stop.report_error(MonitorError::ChannelClosed);
+                        return;
+                    }
+                }
+            }
+        })?;
+
+        Ok(handle)
+    }
+
+    /// 替换规则集。新规则存在错误级别的诊断时保留旧规则并返回全部诊断
+    /// 生效后发出 `RulesReloaded` 以及由此产生的角色变化 (在 `start` 之前调用则在启动时生效)
+    pub fn reload(&self, rules: Vec<DeviceRule>) -> Result<(), Vec<ConfigDiagnostic>> {
+        let diagnostics = validate_rules(&rules);
+        if diagnostics.iter().any(ConfigDiagnostic::is_error) {
+            return Err(diagnostics);
+        }
+        let _ = self.control_tx.send(Control::Rules(rules));
+        Ok(())
+    }
+
+    /// 监听规则文件, 文件变化且通过校验后自动替换规则集, 未通过时发出 `RulesRejected`
+    /// 再次调用会替换之前的监听
+    pub fn watch_rules(&self, path: impl AsRef<Path>) -> Result<()> {
+        let control_tx = self.control_tx.clone();
+        let watcher = RulesWatcher::new(path, move |result| {
+            let control = match result {
+                Ok(rule_set) => Control::Rules(rule_set.rules),
+                Err(diagnostics) => Control::Rejected(diagnostics),
+            };
+            let _ = control_tx.send(control);
+        })?;
+
+        *self.watcher.lock().unwrap() = Some(watcher);
+        Ok(())
+    }
+
+    /// 查询某个角色当前绑定的设备
+    pub fn binding(&self, role: &str) -> Option<ResolvedDevice> {
+        self.state.lock().unwrap().bindings.get(role).cloned()
+    }
+
+    /// 当前所有的绑定关系 (按规则顺序)
+    pub fn bindings(&self) -> Vec<ResolvedDevice> {
+        let state = self.state.lock().unwrap();
+        state
+            .rules
+            .iter()
+            .filter_map(|rule| state.bindings.get(&rule.role).cloned())
+            .collect()
+    }
+
+    /// 上一次分配中落选的候选及原因 (用于排查 "为什么这个设备没有绑定到该角色")
+    pub fn rejections(&self) -> Vec<Rejection> {
+        self.state.lock().unwrap().rejected.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        AmbiguityPolicy, MatchMethod, SimulatedMonitor,
+        test_support::{device, rule, rule_at},
+    };
+
+    #[test]
+    fn hotplug_binds_unbinds_and_binds_again() {
+        let mut state = ResolverState::new(vec![rule("arm", "A")]);
+
+        let events = state.handle(DeviceEvent::Attached(device("A", "1-1")));
+        assert!(matches!(
+            events.as_slice(),
+            [RoleEvent::RoleAttached(dev)] if dev.role == "arm" && dev.device.port_path == "1-1"
+        ));
+        assert!(
+            state
+                .handle(DeviceEvent::Attached(device("B", "1-2")))
+                .is_empty()
+        );
+
+        let events = state.handle(DeviceEvent::Detached("/sys/devices/1-1".to_string()));
+        assert!(matches!(
+            events.as_slice(),
+            [RoleEvent::RoleDetached { role, last_device }]
+                if role == "arm" && last_device.port_path == "1-1"
+        ));
+        assert!(state.bindings.is_empty());
+
+        // Unknown or repeated removals change nothing
+        // 未知的或重复的移除不会产生任何事件
+        assert!(
+            state
+                .handle(DeviceEvent::Detached("/sys/devices/1-1".to_string()))
+                .is_empty()
+        );
+
+        // The same serial on another port binds the role again
+        // 同一序列号出现在另一个端口上, 角色重新绑定
+        let events = state.handle(DeviceEvent::Attached(device("A", "1-3")));
+        assert!(matches!(
+            events.as_slice(),
+            [RoleEvent::RoleAttached(dev)] if dev.role == "arm" && dev.device.port_path == "1-3"
+        ));
+    }
+
+    #[test]
+    fn stronger_device_rebinds_the_role() {
+        let mut state = ResolverState::new(vec![rule_at("arm", Some("A"), Some("1-1"))]);
+
+        // A device without the serial on the configured port binds through the fallback
+        // 配置端口上没有该序列号的设备通过拓扑兜底绑定
+        let events = state.handle(DeviceEvent::Attached(device("X", "1-1")));
+        assert!(matches!(
+            events.as_slice(),
+            [RoleEvent::RoleAttached(dev)] if dev.match_method == MatchMethod::TopologyFallback
+        ));
+
+        // The real serial shows up elsewhere and takes the role over
+        // 真正的序列号出现在别处, 角色换绑到它
+        let events = state.handle(DeviceEvent::Attached(device("A", "1-2")));
+        assert!(matches!(
+            events.as_slice(),
+            [RoleEvent::RoleRebound { role, previous, current }]
+                if role == "arm"
+                    && previous.port_path == "1-1"
+                    && current.device.port_path == "1-2"
+                    && current.match_method == MatchMethod::SerialExact
+        ));
+
+        // Once it is gone the fallback device is bound again
+        // 它被拔出后, 兜底设备重新绑定
+        let events = state.handle(DeviceEvent::Detached("/sys/devices/1-2".to_string()));
+        assert!(matches!(
+            events.as_slice(),
+            [RoleEvent::RoleRebound { current, .. }] if current.device.port_path == "1-1"
+        ));
+    }
+
+    #[test]
+    fn resolver_tracks_bindings_from_its_monitor() {
+        let monitor = SimulatedMonitor::new();
+        monitor.plug(device("A", "1-1"));
+        let resolver = Resolver::with_monitor(
+            vec![rule("arm", "A"), rule("gripper", "B")],
+            Box::new(monitor.clone()),
+        );
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let _handle = resolver.start(tx).unwrap();
+        let next = || rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        assert!(matches!(next(), RoleEvent::RoleAttached(dev) if dev.role == "arm"));
+        monitor.plug(device("B", "1-2"));
+        assert!(matches!(next(), RoleEvent::RoleAttached(dev) if dev.role == "gripper"));
+        assert_eq!(resolver.bindings().len(), 2);
+
+        monitor.unplug("/sys/devices/1-1");
+        assert!(matches!(next(), RoleEvent::RoleDetached { role, .. } if role == "arm"));
+        assert!(resolver.binding("arm").is_none());
+        assert_eq!(
+            resolver.binding("gripper").map(|b| b.device.port_path),
+            Some("1-2".to_string())
+        );
+    }
+
+    #[test]
+    fn second_start_is_refused_while_the_first_run_is_alive() {
+        let monitor = SimulatedMonitor::new();
+        monitor.plug(device("A", "1-1"));
+        let resolver = Resolver::with_monitor(vec![rule("arm", "A")], Box::new(monitor));
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let handle = resolver.start(tx).unwrap();
+        let (second_tx, _second_rx) = crossbeam_channel::unbounded();
+        assert!(resolver.start(second_tx).is_err());
+        assert!(matches!(
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            RoleEvent::RoleAttached(dev) if dev.role == "arm"
+        ));
+
+        // Once the first run is stopped the resolver starts again from scratch
+        // 第一次运行停止后, 解析器可以重新启动, 并从头开始
+        drop(handle);
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let _handle = resolver.start(tx).unwrap();
+        assert!(matches!(
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            RoleEvent::RoleAttached(dev) if dev.role == "arm"
+        ));
+    }
+
+    #[test]
+    fn ambiguity_is_reported_once_and_refusal_leaves_the_role_unbound() {
+        let refusing = DeviceRule {
+            on_ambiguous: AmbiguityPolicy::Refuse,
+            ..rule_at("arm", None, Some("1-*"))
+        };
+        let mut state = ResolverState::new(vec![refusing]);
+        state.handle(DeviceEvent::Attached(device("A", "1-1")));
+
+        let events = state.handle(DeviceEvent::Attached(device("B", "1-2")));
+        assert!(matches!(
+            events.as_slice(),
+            [
+                RoleEvent::RoleDetached { role, .. },
+                RoleEvent::Ambiguous { candidates, refused: true, .. },
+            ] if role == "arm" && candidates.len() == 2
+        ));
+        assert!(state.bindings.is_empty());
+
+        // The same candidate set is not reported again
+        // 相同的候选集合不会重复上报
+        assert!(
+            state
+                .handle(DeviceEvent::Updated(RawDeviceInfo {
+                    product: Some("Arm".to_string()),
+                    ..device("B", "1-2")
+                }))
+                .is_empty()
+        );
+
+        let events = state.handle(DeviceEvent::Detached("/sys/devices/1-1".to_string()));
+        assert!(matches!(
+            events.as_slice(),
+            [RoleEvent::RoleAttached(dev)] if dev.device.port_path == "1-2"
+        ));
+    }
+
+    #[test]
+    fn reload_rebinds_detaches_and_attaches() {
+        let mut state = ResolverState::new(vec![rule("arm", "A"), rule("gripper", "C")]);
+        state.handle(DeviceEvent::Attached(device("A", "1-1")));
+        state.handle(DeviceEvent::Attached(device("B", "1-2")));
+        state.handle(DeviceEvent::Attached(device("C", "1-3")));
+
+        let events = state.set_rules(vec![rule("arm", "B"), rule("camera", "C")]);
+        let summary: Vec<String> = events
+            .iter()
+            .map(|e| match e {
+                RoleEvent::RulesReloaded { rule_count } => format!("reloaded {}", rule_count),
+                RoleEvent::RoleDetached { role, .. } => format!("detached {}", role),
+                RoleEvent::RoleRebound {
+                    role,
+                    previous,
+                    current,
+                } => format!(
+                    "rebound {} {}->{}",
+                    role, previous.port_path, current.device.port_path
+                ),
+                RoleEvent::RoleAttached(dev) => format!("attached {}", dev.role),
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            [
+                "reloaded 2",
+                "detached gripper",
+                "rebound arm 1-1->1-2",
+                "attached camera"
+            ]
+        );
+    }
+
+    #[test]
+    fn device_taken_from_another_role_is_not_a_rebind() {
+        let mut state = ResolverState::new(vec![rule("arm", "A"), rule("gripper", "B")]);
+        state.handle(DeviceEvent::Attached(device("A", "1-1")));
+        state.handle(DeviceEvent::Attached(device("B", "1-2")));
+
+        // arm moves to the gripper's device
+        // arm 换到了 gripper 原来的设备上
+        let events = state.set_rules(vec![rule("arm", "B")]);
+        assert!(
+            events
+                .iter()
+                .all(|e| !matches!(e, RoleEvent::RoleRebound { .. }))
+        );
+        assert_eq!(state.bindings["arm"].device.port_path, "1-2");
+    }
+
+    #[test]
+    fn late_tty_node_updates_the_bound_role() {
+        let mut state = ResolverState::new(vec![
+            rule("arm", "A"),
+            DeviceRule {
+                driver: Some("ch341".to_string()),
+                ..rule("gripper", "B")
+            },
+        ]);
+        state.handle(DeviceEvent::Attached(device("A", "1-1")));
+        state.handle(DeviceEvent::Attached(device("B", "1-2")));
+        assert!(!state.bindings.contains_key("gripper"));
+
+        let with_tty = |dev: RawDeviceInfo, tty: &str| RawDeviceInfo {
+            system_path_alt: Some(tty.to_string()),
+            drivers: vec!["ch341".to_string()],
+            ..dev
+        };
+
+        let events = state.handle(DeviceEvent::Updated(with_tty(
+            device("A", "1-1"),
+            "/dev/ttyUSB0",
+        )));
+        assert!(matches!(
+            events.as_slice(),
+            [RoleEvent::RoleUpdated(dev)]
+                if dev.role == "arm" && dev.device.system_path_alt.as_deref() == Some("/dev/ttyUSB0")
+        ));
+
+        // The driver only shows up with the tty, so the gripper is attached, not updated
+        // 驱动与 tty 一起出现, 因此 gripper 是新绑定, 而不是更新
+        let events = state.handle(DeviceEvent::Updated(with_tty(
+            device("B", "1-2"),
+            "/dev/ttyUSB1",
+        )));
+        assert!(matches!(
+            events.as_slice(),
+            [RoleEvent::RoleAttached(dev)] if dev.role == "gripper"
+        ));
+
+        // Repeated or unknown updates are ignored
+        // 重复的或未知设备的更新被忽略
+        let events = state.handle(DeviceEvent::Updated(with_tty(
+            device("B", "1-2"),
+            "/dev/ttyUSB1",
+        )));
+        assert!(events.is_empty());
+        assert!(
+            state
+                .handle(DeviceEvent::Updated(device("C", "1-3")))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn invalid_rules_are_refused() {
+        let resolver = Resolver::with_monitor(vec![rule("arm", "A")], get_monitor());
+        let err = resolver
+            .reload(vec![rule("arm", "A"), rule("arm", "B")])
+            .unwrap_err();
+        assert!(err.iter().any(ConfigDiagnostic::is_error));
+        assert_eq!(resolver.state.lock().unwrap().rules.len(), 1);
+    }
+}
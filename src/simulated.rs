@@ -0,0 +1,389 @@
+// Simulated monitor: a scripted device set for tests and demos without hardware
+// 模拟监听器: 由脚本控制设备集合, 用于没有硬件时的测试与演示
+//
+// The device set is changed from code (`plug` / `unplug` / `fail` / `recover`) or by playing a
+// `Scenario`, which adds delays and bursts and can be loaded from a JSON, TOML or YAML file.
+// Every started handle receives the same events a real backend would send: the present devices
+// on start, then Attached / Detached / Updated, and during a simulated outage nothing until the
+// recovery resyncs the missed changes.
+// 设备集合可以通过代码 (`plug` / `unplug` / `fail` / `recover`) 修改, 也可以播放一个 `Scenario`,
+// 后者支持延时与突发, 并可以从 JSON、TOML 或 YAML 文件加载。每个启动的句柄收到的事件与真实后端一致:
+// 启动时上报在线设备, 之后是 Attached / Detached / Updated; 模拟中断期间不发送事件, 恢复时补发漏掉的变化。
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ConfigFormat, DeviceEvent, DeviceMonitor, MonitorError, MonitorHandle, RawDeviceInfo,
+    StopSignal, config::strip_json_comments,
+};
+
+/// 场景中的一步
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    /// 插入设备 (system_path 已存在时视为更新)
+    Plug(RawDeviceInfo),
+    /// 按 system_path 拔出设备
+    Unplug(String),
+    /// 更新已在线设备的信息 (例如 tty 节点稍后出现)
+    Update(RawDeviceInfo),
+    /// 等待若干毫秒
+    DelayMs(u64),
+    /// 连续重复若干次, 中间没有延时 (例如接触不良导致的反复插拔)
+    Burst { times: usize, steps: Vec<Step> },
+    /// 模拟与系统的连接中断
+    Fail(String),
+    /// 连接恢复, 补发中断期间的变化
+    Recover,
+}
+
+/// 一段可以播放的场景
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scenario {
+    // `{plug: ...}` instead of YAML's `!plug ...` tags, the same shape in every format
+    // 使用 `{plug: ...}` 而不是 YAML 的 `!plug ...` 标签, 各种格式的写法保持一致
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    pub steps: Vec<Step>,
+}
+
+impl Scenario {
+    /// 读取场景文件, 按扩展名 (或内容) 判断格式
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let format =
+            ConfigFormat::from_path(path).unwrap_or_else(|| ConfigFormat::detect(&content));
+        Self::parse(&content, format).with_context(|| path.display().to_string())
+    }
+
+    /// 按指定格式解析
+    pub fn parse(content: &str, format: ConfigFormat) -> Result<Self> {
+        Ok(match format {
+            ConfigFormat::Json => serde_json::from_str(&strip_json_comments(content))?,
+            ConfigFormat::Toml => toml::from_str(content)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(content)?,
+        })
+    }
+}
+
+// One started handle
+// 一个已启动的句柄
+struct Subscriber {
+    id: u64,
+    tx: Sender<DeviceEvent>,
+    signal: Arc<StopSignal>,
+}
+
+#[derive(Default)]
+struct SimState {
+    // What is plugged in right now
+    // 当前实际插着的设备
+    devices: BTreeMap<String, RawDeviceInfo>,
+    // What the subscribers have been told, differs from `devices` only during an outage
+    // 已经告知订阅者的设备, 只有中断期间才会与 `devices` 不同
+    reported: BTreeMap<String, RawDeviceInfo>,
+    outage: Option<MonitorError>,
+    subscribers: Vec<Subscriber>,
+    next_id: u64,
+}
+
+impl SimState {
+    fn broadcast(&mut self, events: &[DeviceEvent]) {
+        self.subscribers.retain(|s| {
+            let open = events.iter().all(|e| s.tx.send(e.clone()).is_ok());
+            if !open {
+                s.signal.report_error(MonitorError::ChannelClosed);
+            }
+            open
+        });
+    }
+
+    // Bring `reported` in line with `devices`, unless the connection is down
+    // 使 `reported` 与 `devices` 保持一致 (连接中断时除外)
+    fn sync(&mut self) {
+        if self.outage.is_some() {
+            return;
+        }
+
+        let mut events = vec![];
+        for path in self.reported.keys() {
+            if !self.devices.contains_key(path) {
+                events.push(DeviceEvent::Detached(path.clone()));
+            }
+        }
+        for (path, device) in &self.devices {
+            match self.reported.get(path) {
+                None => events.push(DeviceEvent::Attached(device.clone())),
+                Some(old) if old != device => events.push(DeviceEvent::Updated(device.clone())),
+                Some(_) => {}
+            }
+        }
+        self.reported = self.devices.clone();
+        self.broadcast(&events);
+    }
+}
+
+/// 模拟监听器 (克隆出的实例共享同一个设备集合)
+#[derive(Clone, Default)]
+pub struct SimulatedMonitor {
+    state: Arc<Mutex<SimState>>,
+    scenario: Option<Scenario>,
+}
+
+impl SimulatedMonitor {
+    /// 创建一个没有设备的模拟监听器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 创建一个模拟监听器, 每次 `start` 时在后台线程中播放 `scenario`
+    pub fn with_scenario(scenario: Scenario) -> Self {
+        Self {
+            scenario: Some(scenario),
+            ..Default::default()
+        }
+    }
+
+    /// 插入设备 (system_path 已存在时视为更新)
+    pub fn plug(&self, device: RawDeviceInfo) {
+        let mut state = self.state.lock().unwrap();
+        state.devices.insert(device.system_path.clone(), device);
+        state.sync();
+    }
+
+    /// 拔出设备
+    pub fn unplug(&self, system_path: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.devices.remove(system_path);
+        state.sync();
+    }
+
+    /// 更新已在线设备的信息, 设备不在线时忽略
+    pub fn update(&self, device: RawDeviceInfo) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(known) = state.devices.get_mut(&device.system_path) {
+            *known = device;
+            state.sync();
+        }
+    }
+
+    /// 模拟连接中断: 之后的变化在 `recover` 之前不会上报
+    pub fn fail(&self, error: MonitorError) {
+        let mut state = self.state.lock().unwrap();
+        if state.outage.is_some() {
+            return;
+        }
+        for s in &state.subscribers {
+            s.signal.report_error(error.clone());
+        }
+        state.broadcast(&[DeviceEvent::MonitorError(error.clone())]);
+        state.outage = Some(error);
+    }
+
+    /// 模拟连接恢复: 补发中断期间的变化, 然后发送 `MonitorRecovered`
+    pub fn recover(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.outage.take().is_none() {
+            return;
+        }
+        state.sync();
+        for s in &state.subscribers {
+            s.signal.report_ok();
+        }
+        state.broadcast(&[DeviceEvent::MonitorRecovered]);
+    }
+
+    /// 当前插着的设备
+    pub fn devices(&self) -> Vec<RawDeviceInfo> {
+        self.state
+            .lock()
+            .unwrap()
+            .devices
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// 在当前线程中按顺序执行场景 (延时会阻塞当前线程)
+    pub fn run(&self, scenario: &Scenario) {
+        self.run_steps(&scenario.steps, None);
+    }
+
+    // Returns false if a stop request interrupted a delay
+    // 延时被停止请求打断时返回 false
+    fn run_steps(&self, steps: &[Step], stop: Option<&StopSignal>) -> bool {
+        for step in steps {
+            match step {
+                Step::Plug(device) => self.plug(device.clone()),
+                Step::Unplug(path) => self.unplug(path),
+                Step::Update(device) => self.update(device.clone()),
+                Step::DelayMs(ms) => {
+                    let delay = Duration::from_millis(*ms);
+                    match stop {
+                        Some(stop) => {
+                            if stop.wait_timeout(delay) {
+                                return false;
+                            }
+                        }
+                        None => thread::sleep(delay),
+                    }
+                }
+                Step::Burst { times, steps } => {
+                    for _ in 0..*times {
+                        if !self.run_steps(steps, stop) {
+                            return false;
+                        }
+                    }
+                }
+                Step::Fail(message) => self.fail(MonitorError::Socket(message.clone())),
+                Step::Recover => self.recover(),
+            }
+        }
+        true
+    }
+}
+
+impl DeviceMonitor for SimulatedMonitor {
+    fn start(&self, tx: Sender<DeviceEvent>) -> Result<MonitorHandle> {
+        let monitor = self.clone();
+        let state = Arc::clone(&self.state);
+        let handle = MonitorHandle::spawn("simulated-monitor", move |stop| {
+            let id = {
+                let mut state = state.lock().unwrap();
+                let id = state.next_id;
+                state.next_id += 1;
+
+                // A new subscriber learns what the monitor currently knows
+                // 新的订阅者先收到监听器当前已知的设备
+                let present: Vec<DeviceEvent> = state
+                    .reported
+                    .values()
+                    .cloned()
+                    .map(DeviceEvent::Attached)
+                    .collect();
+                if present.iter().all(|e| tx.send(e.clone()).is_ok()) {
+                    if let Some(error) = &state.outage {
+                        stop.report_error(error.clone());
+                    }
+                    state.subscribers.push(Subscriber {
+                        id,
+                        tx,
+                        signal: Arc::clone(&stop),
+                    });
+                } else {
+                    stop.report_error(MonitorError::ChannelClosed);
+                }
+                id
+            };
+
+            if let Some(scenario) = &monitor.scenario {
+                monitor.run_steps(&scenario.steps, Some(&stop));
+            }
+            while !stop.wait_timeout(Duration::from_secs(1)) {}
+
+            state.lock().unwrap().subscribers.retain(|s| s.id != id);
+        })?;
+
+        Ok(handle)
+    }
+
+    fn scan_now(&self) -> Result<Vec<RawDeviceInfo>> {
+        Ok(self.devices())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MonitorHealth, Resolver, RoleEvent,
+        test_support::{device, rule},
+    };
+
+    fn summary(events: impl IntoIterator<Item = DeviceEvent>) -> Vec<String> {
+        events
+            .into_iter()
+            .map(|e| match e {
+                DeviceEvent::Attached(dev) => format!("+{}", dev.port_path),
+                DeviceEvent::Detached(path) => {
+                    format!("-{}", path.trim_start_matches("/sys/devices/"))
+                }
+                DeviceEvent::Updated(dev) => format!("~{}", dev.port_path),
+                DeviceEvent::MonitorError(_) => "error".to_string(),
+                DeviceEvent::MonitorRecovered => "recovered".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn outage_hides_changes_until_recovery() {
+        let monitor = SimulatedMonitor::new();
+        monitor.plug(device("A", "1-1"));
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let handle = monitor.start(tx).unwrap();
+        assert_eq!(summary([rx.recv().unwrap()]), ["+1-1"]);
+
+        monitor.plug(device("B", "1-2"));
+        monitor.fail(MonitorError::Socket("unplugged hub".to_string()));
+        monitor.unplug("/sys/devices/1-1");
+        monitor.plug(device("C", "1-3"));
+        assert!(matches!(handle.health(), MonitorHealth::Degraded(_)));
+        monitor.recover();
+        assert_eq!(handle.health(), MonitorHealth::Running);
+
+        assert_eq!(
+            summary(rx.try_iter()),
+            ["+1-2", "error", "-1-1", "+1-3", "recovered"]
+        );
+    }
+
+    #[test]
+    fn scenario_drives_the_resolver() {
+        let scenario = Scenario::parse(
+            r#"
+            steps:
+              - plug: { vid: "0x1a86", pid: "0x55d3", serial: "A", system_path: "/sys/a" }
+              - burst:
+                  times: 3
+                  steps:
+                    - unplug: /sys/a
+                    - plug: { vid: "0x1a86", pid: "0x55d3", serial: "A", system_path: "/sys/a" }
+              - delay_ms: 10
+              - unplug: /sys/a
+            "#,
+            ConfigFormat::Yaml,
+        )
+        .unwrap();
+
+        let resolver = Resolver::with_monitor(
+            vec![rule("arm", "A")],
+            Box::new(SimulatedMonitor::with_scenario(scenario)),
+        );
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let _handle = resolver.start(tx).unwrap();
+
+        let events: Vec<String> = rx
+            .iter()
+            .take(8)
+            .map(|e| match e {
+                RoleEvent::RoleAttached(dev) => format!("attached {}", dev.role),
+                RoleEvent::RoleDetached { role, .. } => format!("detached {}", role),
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(events, ["attached arm", "detached arm"].repeat(4));
+    }
+}
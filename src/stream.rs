@@ -0,0 +1,156 @@
+// Async API (feature "tokio"): device events as a `Stream`, and an async `scan`
+// 异步接口 (feature "tokio"): 以 `Stream` 形式提供设备事件, 以及异步的 `scan`
+//
+// On Linux `watch()` registers the udev socket with the tokio reactor, so no thread is
+// involved. Other platforms (and any `DeviceMonitor` passed to `watch_monitor`) keep their
+// listener thread; the stream owns it and stops it when dropped.
+// The Linux stream does not reconnect: a socket error ends it, where a listener thread would
+// back off and reopen the socket.
+// 在 Linux 上 `watch()` 把 udev socket 注册到 tokio reactor, 不需要任何线程。其他平台
+// (以及传给 `watch_monitor` 的任意 `DeviceMonitor`) 仍使用各自的监听线程; stream 持有该线程,
+// 被 drop 时停止它。
+// Linux 上的 stream 不会重连: socket 出错即结束, 而监听线程会退避后重新打开 socket。
+
+use std::{
+    future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use anyhow::Result;
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+#[cfg(target_os = "linux")]
+use crate::platform::linux::UdevStream;
+use crate::{DeviceEvent, DeviceMonitor, MonitorError, MonitorHandle, RawDeviceInfo, get_monitor};
+
+/// 设备事件流, 事件与 `DeviceMonitor::start` 发送的相同 (先是在线设备的 Attached)
+pub struct DeviceStream {
+    inner: Inner,
+}
+
+enum Inner {
+    #[cfg(target_os = "linux")]
+    Udev(Box<UdevStream>),
+    // Events forwarded from a monitor thread; the handle stops it when the stream is dropped
+    // 从监听线程转发的事件; stream 被 drop 时句柄会停止该线程
+    Thread {
+        rx: mpsc::UnboundedReceiver<DeviceEvent>,
+        _handle: MonitorHandle,
+    },
+}
+
+impl DeviceStream {
+    /// 等待下一个事件, 等价于 `StreamExt::next`
+    pub async fn next(&mut self) -> Option<DeviceEvent> {
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for DeviceStream {
+    type Item = DeviceEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<DeviceEvent>> {
+        match &mut self.get_mut().inner {
+            #[cfg(target_os = "linux")]
+            Inner::Udev(stream) => Pin::new(stream.as_mut()).poll_next(cx),
+            Inner::Thread { rx, .. } => rx.poll_recv(cx),
+        }
+    }
+}
+
+/// 监听当前平台的设备事件, 必须在 tokio 运行时中调用
+///
+/// **注意: 与监听线程不同, Linux 上的 stream 不会自动重连。** udev socket 出错时, stream 在
+/// 发出 `MonitorError` 之后结束 (`next()` 返回 `None`), 调用方需要重新调用 `watch` 才能继续
+/// 接收事件。其他平台的 `watch` 与 `watch_monitor` 转发监听线程的事件, 是否恢复由该线程决定。
+pub fn watch() -> Result<DeviceStream> {
+    #[cfg(target_os = "linux")]
+    return Ok(DeviceStream {
+        inner: Inner::Udev(Box::new(UdevStream::open()?)),
+    });
+
+    #[cfg(not(target_os = "linux"))]
+    watch_monitor(get_monitor().as_ref())
+}
+
+/// 以 stream 的形式接收任意监听器的事件 (例如 `SimulatedMonitor` 或 `ReplayMonitor`)
+pub fn watch_monitor(monitor: &dyn DeviceMonitor) -> Result<DeviceStream> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let (dev_tx, dev_rx) = crossbeam_channel::unbounded();
+    let monitor = monitor.start(dev_tx)?;
+
+    let handle = MonitorHandle::spawn("stream-bridge", move |stop| {
+        // Stopping the bridge drops `monitor`, which stops the device monitor too
+        // 转发线程退出时会 drop `monitor`, 底层监听器随之停止
+        let _monitor = monitor;
+        let (stop_tx, stop_rx) = crossbeam_channel::bounded(1);
+        stop.on_stop(move || {
+            let _ = stop_tx.try_send(());
+        });
+
+        loop {
+            crossbeam_channel::select! {
+                recv(stop_rx) -> _ => return,
+                recv(dev_rx) -> event => match event {
+                    Ok(event) => {
+                        if tx.send(event).is_err() {
+                            stop.report_error(MonitorError::ChannelClosed);
+                            return;
+                        }
+                    }
+                    // The device monitor thread has exited, the stream ends
+                    // 底层监听线程已退出, stream 随之结束
+                    Err(_) => return,
+                },
+            }
+        }
+    })?;
+
+    Ok(DeviceStream {
+        inner: Inner::Thread {
+            rx,
+            _handle: handle,
+        },
+    })
+}
+
+/// 异步枚举当前在线的设备 (在 tokio 的阻塞线程池中执行)
+pub async fn scan() -> Result<Vec<RawDeviceInfo>> {
+    tokio::task::spawn_blocking(|| get_monitor().scan_now()).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SimulatedMonitor;
+
+    #[test]
+    fn monitor_events_arrive_on_the_stream() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let monitor = SimulatedMonitor::new();
+        monitor.plug(RawDeviceInfo {
+            system_path: "/sys/a".to_string(),
+            ..Default::default()
+        });
+
+        runtime.block_on(async {
+            let mut stream = watch_monitor(&monitor).unwrap();
+            assert!(matches!(
+                stream.next().await,
+                Some(DeviceEvent::Attached(_))
+            ));
+
+            monitor.unplug("/sys/a");
+            assert_eq!(
+                stream.next().await,
+                Some(DeviceEvent::Detached("/sys/a".to_string()))
+            );
+        });
+    }
+}
@@ -0,0 +1,38 @@
+// Fixture factories shared by the unit tests
+// 单元测试共用的测试数据构造函数
+//
+// Every device is a CH343 (1a86:55d3) whose system_path is derived from its port, so tests
+// can unplug it by `/sys/devices/<port>` and match it by serial or by port.
+// 所有设备都是 CH343 (1a86:55d3), system_path 由端口推出, 测试中可以通过
+// `/sys/devices/<port>` 拔出设备, 也可以按序列号或端口匹配。
+
+use crate::{DeviceRule, RawDeviceInfo};
+
+/// CH343 设备, system_path 为 /sys/devices/<port>
+pub(crate) fn device(serial: &str, port: &str) -> RawDeviceInfo {
+    RawDeviceInfo {
+        vid: 0x1a86,
+        pid: 0x55d3,
+        serial: Some(serial.to_string()),
+        port_path: port.to_string(),
+        system_path: format!("/sys/devices/{}", port),
+        ..Default::default()
+    }
+}
+
+/// 按序列号匹配 CH343 的规则
+pub(crate) fn rule(role: &str, serial: &str) -> DeviceRule {
+    rule_at(role, Some(serial), None)
+}
+
+/// 匹配 CH343 的规则, 序列号与端口均可省略
+pub(crate) fn rule_at(role: &str, serial: Option<&str>, port_path: Option<&str>) -> DeviceRule {
+    DeviceRule {
+        role: role.to_string(),
+        vid: 0x1a86,
+        pid: 0x55d3.into(),
+        serial: serial.map(str::to_string),
+        port_path: port_path.map(str::to_string),
+        ..Default::default()
+    }
+}
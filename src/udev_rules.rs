@@ -0,0 +1,496 @@
+// udev rules generation
+// 生成 udev 规则
+//
+// Turns the roles of a rule set into permanent symlinks (`/dev/main_arm` -> `/dev/ttyUSB0`)
+// maintained by udev itself, for deployments that should not depend on a running resolver.
+// The symlink is put on the node the resolver would open: the rule's `node` if it has one,
+// otherwise the tty, or the capture node of a camera.
+// 把规则集中的角色转换为由 udev 自己维护的永久符号链接 (`/dev/main_arm` -> `/dev/ttyUSB0`),
+// 适用于不希望依赖常驻解析器的部署。链接建立在解析器会打开的节点上: 规则配置了 `node` 时为该节点,
+// 否则为 tty, 或摄像头的视频采集节点。
+//
+// udev rules can't rank candidates, so everything that depends on match strength (serial before
+// topology fallback, ambiguity handling) is approximated and reported as a warning. A rule set
+// that would produce a broken file (a value udev can't quote, two roles sharing one symlink)
+// is an error instead.
+// udev 规则无法对候选排序, 因此依赖匹配强度的部分 (序列号优先于拓扑回退、歧义处理) 只能近似,
+// 并以警告的形式报告。会生成错误文件的规则集 (udev 无法引用的值、两个角色共用一个链接) 则报错。
+
+use std::{collections::HashMap, fmt};
+
+use anyhow::{Result, bail};
+
+use crate::{DeviceRule, NodeKind, PidMatch, RoleId, SerialFallback};
+
+/// 生成的规则文件的推荐文件名 (放在 /etc/udev/rules.d/ 下)
+pub const UDEV_RULES_FILE: &str = "99-usb-resolver.rules";
+
+// Larger PID ranges are not expanded into alternatives
+// 超过该大小的 PID 区间不会被展开为候选列表
+const MAX_PID_ALTERNATIVES: usize = 64;
+
+/// 生成 udev 规则的选项
+#[derive(Debug, Clone, Default)]
+pub struct UdevOptions {
+    /// 节点的权限 (例如 "0666")
+    pub mode: Option<String>,
+    /// 节点的属组 (例如 "dialout")
+    pub group: Option<String>,
+}
+
+/// 生成结果
+#[derive(Debug, Clone, Default)]
+pub struct UdevRules {
+    /// 规则文件的内容
+    pub content: String,
+    /// 无法用 udev 语法准确表达的规则
+    pub warnings: Vec<UdevWarning>,
+}
+
+/// 一条规则在转换时丢失或近似的部分
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UdevWarning {
+    pub rule_index: usize,
+    pub role: RoleId,
+    pub message: String,
+}
+
+impl fmt::Display for UdevWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "warning [rule #{}]: role '{}': {}",
+            self.rule_index, self.role, self.message
+        )
+    }
+}
+
+/// 把规则集转换为 udev 规则文件
+/// 值中含有引号、反斜杠、换行或 `|`, 或角色无法成为唯一的链接名时返回错误
+pub fn generate_udev_rules(rules: &[DeviceRule], options: &UdevOptions) -> Result<UdevRules> {
+    let mut result = UdevRules::default();
+    result.content.push_str(&format!(
+        "# Generated by usb-resolver from {} rule(s), do not edit.\n\
+         # Install as /etc/udev/rules.d/{}, then run:\n\
+         #     udevadm control --reload && udevadm trigger\n",
+        rules.len(),
+        UDEV_RULES_FILE
+    ));
+
+    let mut links: HashMap<String, (usize, &RoleId)> = HashMap::new();
+    for (index, rule) in rules.iter().enumerate() {
+        let mut warn = |message: String| {
+            result.warnings.push(UdevWarning {
+                rule_index: index,
+                role: rule.role.clone(),
+                message,
+            })
+        };
+
+        let link = symlink_name(&rule.role);
+        if link.is_empty() {
+            warn("the role can't be used as a symlink name, skipped".to_string());
+            continue;
+        }
+        if link == "." || link == ".." {
+            bail!(
+                "rule #{}: role '{}' can't be used as a symlink name",
+                index,
+                rule.role
+            );
+        }
+        if let Some((first, role)) = links.insert(link.clone(), (index, &rule.role)) {
+            bail!(
+                "rule #{}: role '{}' becomes the symlink /dev/{}, like role '{}' of rule #{}",
+                index,
+                rule.role,
+                link,
+                role,
+                first
+            );
+        }
+
+        let lines = match rule_lines(rule, &link, options, &mut warn) {
+            Ok(Some(lines)) => lines,
+            Ok(None) => continue,
+            Err(e) => bail!("rule #{}: role '{}': {}", index, rule.role, e),
+        };
+
+        result.content.push_str(&format!("\n# {}\n", rule.role));
+        for line in lines {
+            result.content.push_str(&line);
+            result.content.push('\n');
+        }
+    }
+
+    Ok(result)
+}
+
+// The udev lines for one rule, None if the rule can't be expressed at all
+// 一条规则对应的 udev 规则行, 完全无法表达时为 None
+fn rule_lines(
+    rule: &DeviceRule,
+    link: &str,
+    options: &UdevOptions,
+    warn: &mut impl FnMut(String),
+) -> Result<Option<Vec<String>>> {
+    let Some(pid) = pid_value(&rule.pid) else {
+        warn(format!(
+            "the pid range is larger than {} ids, skipped",
+            MAX_PID_ALTERNATIVES
+        ));
+        return Ok(None);
+    };
+
+    // Keys every line of this rule shares. All ATTRS keys must hold on the same ancestor,
+    // so only attributes of the usb_device itself use ATTRS, the rest comes from the
+    // properties usb_id puts on the node.
+    // 该规则每一行共有的匹配条件。所有 ATTRS 必须在同一个祖先设备上成立, 因此只有 usb_device
+    // 自身的属性使用 ATTRS, 其余来自 usb_id 写在节点上的属性。
+    let mut common = vec![
+        format!("ATTRS{{idVendor}}==\"{:04x}\"", rule.vid),
+        format!("ATTRS{{idProduct}}==\"{}\"", pid),
+    ];
+    if let Some(manufacturer) = &rule.manufacturer {
+        common.push(format!(
+            "ATTRS{{manufacturer}}==\"{}\"",
+            quotable("manufacturer", manufacturer)?
+        ));
+    }
+    if let Some(product) = &rule.product {
+        common.push(format!(
+            "ATTRS{{product}}==\"{}\"",
+            quotable("product", product)?
+        ));
+    }
+    if let Some(bcd_device) = rule.bcd_device {
+        common.push(format!("ATTRS{{bcdDevice}}==\"{:04x}\"", bcd_device));
+    }
+    if let Some(class) = rule.device_class {
+        common.push(format!("ATTRS{{bDeviceClass}}==\"{:02x}\"", class));
+    }
+    if let Some(class) = rule.interface_class {
+        // ":cssspp:" for every interface of the device
+        // 设备的每个接口都以 ":类子类协议:" 的形式列出
+        common.push(format!(
+            "ENV{{ID_USB_INTERFACES}}==\"*:{:02x}????:*\"",
+            class
+        ));
+    }
+    if let Some(driver) = &rule.driver {
+        common.push(format!(
+            "ENV{{ID_USB_DRIVER}}==\"{}\"",
+            quotable("driver", driver)?
+        ));
+        warn("`driver` only checks the interface the node belongs to".to_string());
+    }
+
+    // Which node(s) get the symlink
+    // 链接建立在哪些节点上
+    let nodes: Vec<Vec<String>> = match &rule.node {
+        Some(node) => {
+            let mut keys = vec![];
+            match (node.kind, node.subsystem.as_deref()) {
+                (None | Some(NodeKind::Other), Some(subsystem)) => keys.push(format!(
+                    "SUBSYSTEM==\"{}\"",
+                    quotable("node.subsystem", subsystem)?
+                )),
+                (Some(kind), _) => keys.extend(kind_keys(kind)),
+                (None, None) => keys.push("SUBSYSTEM!=\"usb\"".to_string()),
+            }
+            if let Some(interface) = node.interface {
+                keys.push(format!(
+                    "ENV{{ID_USB_INTERFACE_NUM}}==\"{:02x}\"",
+                    interface
+                ));
+            }
+            if node.index.is_some_and(|i| i > 0) {
+                warn(
+                    "`node.index` can't be expressed, every matching node claims the link"
+                        .to_string(),
+                );
+            }
+            vec![keys]
+        }
+        // Like `system_path_alt`: the tty, or the capture node of a camera
+        // 与 `system_path_alt` 相同: tty, 或摄像头的视频采集节点
+        None => vec![kind_keys(NodeKind::Serial), kind_keys(NodeKind::Capture)],
+    };
+
+    // Serial, topology fallback, or both
+    // 序列号、拓扑回退, 或两者都有
+    if let Some(port) = &rule.port_path {
+        quotable("port_path", port)?;
+    }
+    let port = rule.port_path.as_deref().filter(|port| {
+        let linux = !port.starts_with("0x") && !port.contains('\\');
+        if !linux {
+            warn(format!(
+                "port_path '{}' is not a Linux ID_PATH, it is not matched",
+                port
+            ));
+        }
+        linux
+    });
+    let port_key = |port: &str| format!("ENV{{ID_PATH}}==\"{}:*\"", port);
+    let serial_key = |serial: &str| -> Result<String> {
+        Ok(format!(
+            "ATTRS{{serial}}==\"{}\"",
+            quotable("serial", serial)?
+        ))
+    };
+    let identities: Vec<Vec<String>> = match (&rule.serial, port) {
+        (Some(serial), Some(port)) => {
+            let serial_keys = vec![serial_key(serial)?];
+            match rule.serial_fallback {
+                SerialFallback::Never => vec![serial_keys],
+                fallback => {
+                    warn(
+                        "udev can't prefer the serial over the topology fallback, a device at the fallback port may claim the link as well"
+                            .to_string(),
+                    );
+                    let mut fallback_keys = vec![port_key(port)];
+                    if fallback == SerialFallback::OnMissing {
+                        fallback_keys.push("ATTRS{serial}!=\"?*\"".to_string());
+                    }
+                    vec![serial_keys, fallback_keys]
+                }
+            }
+        }
+        (Some(serial), None) => vec![vec![serial_key(serial)?]],
+        (None, Some(port)) => vec![vec![port_key(port)]],
+        (None, None) => {
+            warn("matched by VID/PID only, every such device claims the link".to_string());
+            vec![vec![]]
+        }
+    };
+
+    let mut actions = vec![format!("SYMLINK+=\"{}\"", link)];
+    if let Some(mode) = &options.mode {
+        actions.push(format!("MODE=\"{}\"", quotable("mode", mode)?));
+    }
+    if let Some(group) = &options.group {
+        actions.push(format!("GROUP=\"{}\"", quotable("group", group)?));
+    }
+
+    let mut lines = vec![];
+    for node in &nodes {
+        for identity in &identities {
+            let keys: Vec<&str> = node
+                .iter()
+                .chain(&common)
+                .chain(identity)
+                .chain(&actions)
+                .map(String::as_str)
+                .collect();
+            lines.push(keys.join(", "));
+        }
+    }
+    Ok(Some(lines))
+}
+
+// udev has no escape for a backslash in a plain "..." value, a quote or a line break would end
+// the value or the rule early, and a `|` splits a match into alternatives that other devices
+// can satisfy
+// udev 的普通 "..." 值中无法转义反斜杠, 引号或换行会提前结束该值或整行规则, 而 `|` 会把匹配值
+// 拆成多个候选, 其他设备也可能满足
+fn quotable<'a>(field: &str, value: &'a str) -> Result<&'a str> {
+    if value.contains(['"', '\\', '\n', '\r', '|']) {
+        bail!(
+            "{} {:?} contains a quote, a backslash, a line break or a '|', which udev rules can't match literally",
+            field,
+            value
+        );
+    }
+    Ok(value)
+}
+
+// Keys selecting a node by its purpose
+// 按用途选择节点的匹配条件
+fn kind_keys(kind: NodeKind) -> Vec<String> {
+    match kind {
+        NodeKind::Serial => vec!["SUBSYSTEM==\"tty\"".to_string()],
+        NodeKind::Capture => vec![
+            "SUBSYSTEM==\"video4linux\"".to_string(),
+            "ENV{ID_V4L_CAPABILITIES}==\"*:capture:*\"".to_string(),
+        ],
+        NodeKind::Metadata => vec![
+            "SUBSYSTEM==\"video4linux\"".to_string(),
+            "ENV{ID_V4L_CAPABILITIES}!=\"*:capture:*\"".to_string(),
+        ],
+        NodeKind::Other => vec!["SUBSYSTEM!=\"tty|video4linux|usb\"".to_string()],
+    }
+}
+
+// udev matches accept "a|b|c" alternatives
+// udev 的匹配值支持 "a|b|c" 形式的候选列表
+fn pid_value(pid: &PidMatch) -> Option<String> {
+    let pids: Vec<u16> = match pid {
+        PidMatch::Exact(pid) => vec![*pid],
+        PidMatch::List(list) => list.clone(),
+        PidMatch::Range { min, max } => {
+            if usize::from(max.saturating_sub(*min)) >= MAX_PID_ALTERNATIVES {
+                return None;
+            }
+            (*min..=*max).collect()
+        }
+    };
+    let pids: Vec<String> = pids.iter().map(|pid| format!("{:04x}", pid)).collect();
+    Some(pids.join("|"))
+}
+
+// "main arm" -> "main_arm"
+fn symlink_name(role: &str) -> String {
+    role.trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NodeSelector, test_support::rule_at};
+
+    fn lines(rules: &UdevRules) -> Vec<&str> {
+        rules
+            .content
+            .lines()
+            .filter(|l| !l.starts_with('#') && !l.is_empty())
+            .collect()
+    }
+
+    #[test]
+    fn serial_rule_links_tty_and_capture_node() {
+        let options = UdevOptions {
+            mode: Some("0660".to_string()),
+            group: Some("dialout".to_string()),
+        };
+        let rules = generate_udev_rules(&[rule_at("main arm", Some("5AB0183575"), None)], &options)
+            .unwrap();
+        assert!(rules.warnings.is_empty());
+        assert_eq!(
+            lines(&rules),
+            [
+                r#"SUBSYSTEM=="tty", ATTRS{idVendor}=="1a86", ATTRS{idProduct}=="55d3", ATTRS{serial}=="5AB0183575", SYMLINK+="main_arm", MODE="0660", GROUP="dialout""#,
+                r#"SUBSYSTEM=="video4linux", ENV{ID_V4L_CAPABILITIES}=="*:capture:*", ATTRS{idVendor}=="1a86", ATTRS{idProduct}=="55d3", ATTRS{serial}=="5AB0183575", SYMLINK+="main_arm", MODE="0660", GROUP="dialout""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn node_selector_and_fallback() {
+        let mut r = rule_at("debug", Some("5AB01*"), Some("pci-0000:00:14.0-usb-0:2"));
+        r.serial_fallback = SerialFallback::OnMissing;
+        r.pid = PidMatch::List(vec![0x55d3, 0x7523]);
+        r.node = Some(NodeSelector {
+            kind: Some(NodeKind::Serial),
+            interface: Some(2),
+            ..Default::default()
+        });
+
+        let rules = generate_udev_rules(&[r], &UdevOptions::default()).unwrap();
+        assert_eq!(
+            lines(&rules),
+            [
+                r#"SUBSYSTEM=="tty", ENV{ID_USB_INTERFACE_NUM}=="02", ATTRS{idVendor}=="1a86", ATTRS{idProduct}=="55d3|7523", ATTRS{serial}=="5AB01*", SYMLINK+="debug""#,
+                r#"SUBSYSTEM=="tty", ENV{ID_USB_INTERFACE_NUM}=="02", ATTRS{idVendor}=="1a86", ATTRS{idProduct}=="55d3|7523", ENV{ID_PATH}=="pci-0000:00:14.0-usb-0:2:*", ATTRS{serial}!="?*", SYMLINK+="debug""#,
+            ]
+        );
+        // The fallback can't be ranked below the serial
+        // 回退无法排在序列号之后
+        assert_eq!(rules.warnings.len(), 1);
+    }
+
+    #[test]
+    fn inexpressible_parts_are_reported() {
+        let mut wide = rule_at("wide", None, None);
+        wide.pid = PidMatch::Range {
+            min: 0x0000,
+            max: 0xffff,
+        };
+        let rules = generate_udev_rules(
+            &[
+                wide,
+                rule_at("loose", None, None),
+                rule_at("mac", Some("5AB0183575"), Some("0x02122000")),
+                rule_at("   ", None, None),
+            ],
+            &UdevOptions::default(),
+        )
+        .unwrap();
+
+        let warned: Vec<(usize, &str)> = rules
+            .warnings
+            .iter()
+            .map(|w| (w.rule_index, w.message.as_str()))
+            .collect();
+        assert_eq!(warned.len(), 4, "{:?}", warned);
+        assert!(warned[0].1.contains("pid range"));
+        assert!(warned[1].1.contains("VID/PID only"));
+        assert!(warned[2].1.contains("not a Linux ID_PATH"));
+        assert!(warned[3].1.contains("symlink name"));
+
+        // Only the loose and the serial rule are written, the serial rule without the port
+        // 只写出了宽松规则与序列号规则, 后者不包含端口
+        assert!(!rules.content.contains("wide"));
+        assert!(rules.content.contains("SYMLINK+=\"mac\""));
+        assert!(!rules.content.contains("0x02122000"));
+        assert_eq!(lines(&rules).len(), 4);
+    }
+
+    #[test]
+    fn values_udev_cant_quote_are_errors() {
+        let mut quoted = rule_at("arm", None, None);
+        quoted.product = Some(r#"USB "Serial""#.to_string());
+        let mut backslash = rule_at("arm", Some(r"5AB0\1"), None);
+        backslash.serial_fallback = SerialFallback::Never;
+        // "A|*" would match any serial
+        // "A|*" 会匹配任意序列号
+        let alternatives = rule_at("arm", Some("A|*"), None);
+        let mut manufacturer = rule_at("arm", None, Some("1-2"));
+        manufacturer.manufacturer = Some("QinHeng|FTDI".to_string());
+
+        for rule in [quoted, backslash, alternatives, manufacturer] {
+            let error = generate_udev_rules(&[rule], &UdevOptions::default()).unwrap_err();
+            assert!(error.to_string().contains("rule #0"), "{}", error);
+        }
+
+        let options = UdevOptions {
+            group: Some("dialout\nRUN+=\"/bin/sh\"".to_string()),
+            ..Default::default()
+        };
+        assert!(generate_udev_rules(&[rule_at("arm", None, None)], &options).is_err());
+    }
+
+    #[test]
+    fn symlink_names_must_be_usable_and_unique() {
+        for role in [".", ".."] {
+            let error = generate_udev_rules(&[rule_at(role, None, None)], &UdevOptions::default())
+                .unwrap_err();
+            assert!(error.to_string().contains("symlink name"), "{}", error);
+        }
+
+        let error = generate_udev_rules(
+            &[
+                rule_at("main arm", Some("A"), None),
+                rule_at("main_arm", Some("B"), None),
+            ],
+            &UdevOptions::default(),
+        )
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("/dev/main_arm, like role 'main arm' of rule #0"),
+            "{}",
+            error
+        );
+    }
+}
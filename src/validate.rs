@@ -0,0 +1,369 @@
+// Rule set validation
+// 规则集校验
+//
+// A typo in the config file must not silently turn into "zero rules" or "a rule that never
+// matches". `validate_rules` inspects a parsed rule set and reports everything suspicious.
+// 配置文件中的笔误不应悄悄变成 "零条规则" 或 "永远不会匹配的规则"。
+// `validate_rules` 检查解析后的规则集, 并报告所有可疑之处。
+
+use std::{collections::HashMap, error::Error, fmt};
+
+use crate::{DeviceRule, PidMatch, RoleId, pattern::check_pattern};
+
+/// 诊断的严重程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// 配置可以使用, 但行为可能与预期不符
+    Warning,
+    /// 配置有误, 不应被使用
+    Error,
+}
+
+/// 一条配置诊断
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDiagnostic {
+    pub severity: Severity,
+    /// 出问题的规则在配置中的下标 (解析错误时为 None)
+    pub rule_index: Option<usize>,
+    pub kind: DiagnosticKind,
+}
+
+/// 诊断类型
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// 配置文件无法解析 (行号与列号从 1 开始, 未知时为 0)
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// role 为空
+    EmptyRole,
+    /// role 重复 (role 在整个文件中必须唯一)
+    DuplicateRole { role: RoleId, first_index: usize },
+    /// 规则永远不会匹配任何设备
+    NeverMatches { role: RoleId, reason: String },
+    /// 规则能匹配的每个设备, 排在它前面的另一条规则都能以相同强度匹配
+    Shadowed { role: RoleId, by: RoleId },
+    /// 规则与另一条规则除 role 外完全相同
+    IdenticalCriteria { role: RoleId, other: RoleId },
+}
+
+impl ConfigDiagnostic {
+    fn error(rule_index: Option<usize>, kind: DiagnosticKind) -> Self {
+        Self {
+            severity: Severity::Error,
+            rule_index,
+            kind,
+        }
+    }
+
+    fn warning(rule_index: Option<usize>, kind: DiagnosticKind) -> Self {
+        Self {
+            severity: Severity::Warning,
+            rule_index,
+            kind,
+        }
+    }
+
+    /// 构造一条解析错误
+    pub fn parse(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self::error(
+            None,
+            DiagnosticKind::Parse {
+                line,
+                column,
+                message: message.into(),
+            },
+        )
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for ConfigDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error")?,
+            Severity::Warning => write!(f, "warning")?,
+        }
+        if let Some(index) = self.rule_index {
+            write!(f, " [rule #{}]", index)?;
+        }
+
+        match &self.kind {
+            DiagnosticKind::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, ": line {}, column {}: {}", line, column, message),
+            DiagnosticKind::EmptyRole => write!(f, ": role must not be empty"),
+            DiagnosticKind::DuplicateRole { role, first_index } => write!(
+                f,
+                ": role '{}' is already used by rule #{}, roles must be unique",
+                role, first_index
+            ),
+            DiagnosticKind::NeverMatches { role, reason } => {
+                write!(f, ": role '{}' can never match: {}", role, reason)
+            }
+            DiagnosticKind::Shadowed { role, by } => write!(
+                f,
+                ": role '{}' is shadowed by '{}': every device it matches is matched equally well by '{}', which is listed first",
+                role, by, by
+            ),
+            DiagnosticKind::IdenticalCriteria { role, other } => write!(
+                f,
+                ": role '{}' has exactly the same matching criteria as '{}', which device gets which role depends on enumeration order",
+                role, other
+            ),
+        }
+    }
+}
+
+impl Error for ConfigDiagnostic {}
+
+/// 校验规则集, 返回所有诊断 (没有问题时为空)
+pub fn validate_rules(rules: &[DeviceRule]) -> Vec<ConfigDiagnostic> {
+    let mut diagnostics = vec![];
+    let mut roles: HashMap<&str, usize> = HashMap::new();
+
+    for (index, rule) in rules.iter().enumerate() {
+        // Unique, non-empty roles
+        // role 非空且唯一
+        if rule.role.trim().is_empty() {
+            diagnostics.push(ConfigDiagnostic::error(
+                Some(index),
+                DiagnosticKind::EmptyRole,
+            ));
+        } else if let Some(&first_index) = roles.get(rule.role.as_str()) {
+            diagnostics.push(ConfigDiagnostic::error(
+                Some(index),
+                DiagnosticKind::DuplicateRole {
+                    role: rule.role.clone(),
+                    first_index,
+                },
+            ));
+        } else {
+            roles.insert(&rule.role, index);
+        }
+
+        if let Some(reason) = never_matches(rule) {
+            diagnostics.push(ConfigDiagnostic::error(
+                Some(index),
+                DiagnosticKind::NeverMatches {
+                    role: rule.role.clone(),
+                    reason,
+                },
+            ));
+            // Comparing a dead rule against the others would only add noise
+            // 一条不可能匹配的规则, 再和其它规则比较只会产生噪音
+            continue;
+        }
+
+        // Compare against every rule listed before this one
+        // 与排在它前面的每条规则比较
+        for other in &rules[..index] {
+            if same_criteria(rule, other) {
+                diagnostics.push(ConfigDiagnostic::warning(
+                    Some(index),
+                    DiagnosticKind::IdenticalCriteria {
+                        role: rule.role.clone(),
+                        other: other.role.clone(),
+                    },
+                ));
+            } else if covers(other, rule) {
+                diagnostics.push(ConfigDiagnostic::warning(
+                    Some(index),
+                    DiagnosticKind::Shadowed {
+                        role: rule.role.clone(),
+                        by: other.role.clone(),
+                    },
+                ));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+// Why a rule can never match, if it can't
+// 规则永远不会匹配的原因
+fn never_matches(rule: &DeviceRule) -> Option<String> {
+    match &rule.pid {
+        PidMatch::List(list) if list.is_empty() => {
+            return Some("the pid list is empty".to_string());
+        }
+        PidMatch::Range { min, max } if min > max => {
+            return Some(format!("the pid range {:#06x}..{:#06x} is empty", min, max));
+        }
+        _ => {}
+    }
+
+    let texts = [
+        ("serial", &rule.serial),
+        ("port_path", &rule.port_path),
+        ("manufacturer", &rule.manufacturer),
+        ("product", &rule.product),
+        ("driver", &rule.driver),
+        (
+            "node.subsystem",
+            &rule.node.as_ref().and_then(|n| n.subsystem.clone()),
+        ),
+    ];
+    for (field, value) in texts {
+        match value.as_deref() {
+            Some("") => return Some(format!("{} is an empty string", field)),
+            Some(value) => {
+                if let Err(e) = check_pattern(value) {
+                    return Some(format!("{}: {}", field, e));
+                }
+            }
+            None => {}
+        }
+    }
+
+    None
+}
+
+// Everything except the role (and the ambiguity policy) is equal
+// 除 role (以及歧义策略) 外全部相同
+fn same_criteria(a: &DeviceRule, b: &DeviceRule) -> bool {
+    a.vid == b.vid && a.pid == b.pid && same_identity(a, b) && same_filters(a, b)
+}
+
+// serial / port_path / fallback decide the match method, so they must be identical for two
+// rules to compete at the same strength
+// serial / port_path / 回退策略决定匹配方法, 两条规则只有在这些字段完全相同时才会以相同强度竞争
+fn same_identity(a: &DeviceRule, b: &DeviceRule) -> bool {
+    a.serial == b.serial
+        && a.port_path == b.port_path
+        && (a.serial.is_none() || a.serial_fallback == b.serial_fallback)
+}
+
+fn same_filters(a: &DeviceRule, b: &DeviceRule) -> bool {
+    a.manufacturer == b.manufacturer
+        && a.product == b.product
+        && a.bcd_device == b.bcd_device
+        && a.device_class == b.device_class
+        && a.interface_class == b.interface_class
+        && a.driver == b.driver
+        && a.node == b.node
+}
+
+// `wide` matches every device `narrow` matches, with the same strength
+// `wide` 能以相同强度匹配 `narrow` 能匹配的每个设备
+fn covers(wide: &DeviceRule, narrow: &DeviceRule) -> bool {
+    // A filter set on `wide` must be set identically on `narrow`
+    // `wide` 上配置的过滤条件, `narrow` 必须配置了相同的值
+    fn looser<T: PartialEq>(wide: &Option<T>, narrow: &Option<T>) -> bool {
+        wide.is_none() || wide == narrow
+    }
+
+    wide.vid == narrow.vid
+        && pid_subset(&narrow.pid, &wide.pid)
+        && same_identity(wide, narrow)
+        && looser(&wide.manufacturer, &narrow.manufacturer)
+        && looser(&wide.product, &narrow.product)
+        && looser(&wide.bcd_device, &narrow.bcd_device)
+        && looser(&wide.device_class, &narrow.device_class)
+        && looser(&wide.interface_class, &narrow.interface_class)
+        && looser(&wide.driver, &narrow.driver)
+        && looser(&wide.node, &narrow.node)
+}
+
+// Every PID `inner` accepts is accepted by `outer`
+// `inner` 接受的每个 PID 都被 `outer` 接受
+fn pid_subset(inner: &PidMatch, outer: &PidMatch) -> bool {
+    match inner {
+        PidMatch::Exact(pid) => outer.contains(*pid),
+        PidMatch::List(list) => list.iter().all(|pid| outer.contains(*pid)),
+        PidMatch::Range { min, max } => (*min..=*max).all(|pid| outer.contains(pid)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{rule, rule_at};
+
+    fn kinds(rules: &[DeviceRule]) -> Vec<DiagnosticKind> {
+        validate_rules(rules).into_iter().map(|d| d.kind).collect()
+    }
+
+    #[test]
+    fn clean_config_has_no_diagnostics() {
+        let rules = [rule("main_arm", "A"), rule("secondary_arm", "B")];
+        assert!(validate_rules(&rules).is_empty());
+    }
+
+    #[test]
+    fn duplicate_and_empty_roles() {
+        let rules = [rule("arm", "A"), rule("arm", "B"), rule(" ", "C")];
+        assert_eq!(
+            kinds(&rules),
+            vec![
+                DiagnosticKind::DuplicateRole {
+                    role: "arm".to_string(),
+                    first_index: 0
+                },
+                DiagnosticKind::EmptyRole,
+            ]
+        );
+    }
+
+    #[test]
+    fn never_matching_rules() {
+        let mut empty_range = rule_at("a", None, None);
+        empty_range.pid = PidMatch::Range {
+            min: 0x55df,
+            max: 0x55d0,
+        };
+        let bad_pattern = rule("b", "5AB[01");
+
+        let diagnostics = validate_rules(&[empty_range, bad_pattern]);
+        assert_eq!(diagnostics.len(), 2);
+        assert!(
+            diagnostics
+                .iter()
+                .all(|d| d.is_error() && matches!(d.kind, DiagnosticKind::NeverMatches { .. }))
+        );
+    }
+
+    #[test]
+    fn identical_and_shadowed_rules() {
+        let mut wide = rule_at("any_ch340", None, None);
+        wide.pid = PidMatch::List(vec![0x55d3, 0x7523]);
+
+        let rules = [
+            wide,
+            rule_at("arm", None, None),
+            rule_at("arm_copy", None, None),
+        ];
+        assert_eq!(
+            kinds(&rules),
+            vec![
+                DiagnosticKind::Shadowed {
+                    role: "arm".to_string(),
+                    by: "any_ch340".to_string()
+                },
+                DiagnosticKind::Shadowed {
+                    role: "arm_copy".to_string(),
+                    by: "any_ch340".to_string()
+                },
+                DiagnosticKind::IdenticalCriteria {
+                    role: "arm_copy".to_string(),
+                    other: "arm".to_string()
+                },
+            ]
+        );
+        assert!(validate_rules(&rules).iter().all(|d| !d.is_error()));
+    }
+
+    #[test]
+    fn serial_rule_is_not_shadowed_by_loose_rule() {
+        let rules = [rule_at("any", None, None), rule("arm", "A")];
+        assert!(validate_rules(&rules).is_empty());
+    }
+}
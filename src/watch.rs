@@ -0,0 +1,199 @@
+// Rules file watching (hot reload)
+// 规则文件监听 (热重载)
+//
+// The parent directory is watched rather than the file itself: most editors save by writing a
+// temporary file and renaming it over the original, which would silently end a watch on the
+// old inode. Bursts of events are debounced, then the file is loaded and validated, and the
+// callback receives either the new rule set or the diagnostics explaining why it was refused.
+// 监听的是所在目录而不是文件本身: 大多数编辑器保存时会先写临时文件再重命名覆盖原文件,
+// 直接监听文件会在旧的 inode 上悄悄失效。连续的事件会先去抖动, 再加载并校验文件,
+// 回调收到新的规则集, 或者说明拒绝原因的诊断。
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use crossbeam_channel::RecvTimeoutError;
+use log::{info, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::{ConfigDiagnostic, MonitorHandle, RuleSet};
+
+// Quiet period after the last file event before reloading
+// 最后一个文件事件之后, 等待多久没有新事件才重新加载
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// 加载并校验规则文件: 存在错误级别的诊断时返回全部诊断
+pub fn load_validated(path: impl AsRef<Path>) -> Result<RuleSet, Vec<ConfigDiagnostic>> {
+    let rule_set = RuleSet::load(path).map_err(|e| match e.downcast_ref::<ConfigDiagnostic>() {
+        Some(diagnostic) => vec![diagnostic.clone()],
+        None => vec![ConfigDiagnostic::parse(0, 0, format!("{:#}", e))],
+    })?;
+
+    let diagnostics = rule_set.validate();
+    if diagnostics.iter().any(ConfigDiagnostic::is_error) {
+        return Err(diagnostics);
+    }
+    Ok(rule_set)
+}
+
+/// 规则文件监听器, 被 drop 时停止监听并等待重载线程退出
+pub struct RulesWatcher {
+    path: PathBuf,
+    _watcher: RecommendedWatcher,
+    _handle: MonitorHandle,
+}
+
+impl RulesWatcher {
+    /// 监听规则文件, 每次文件变化后以校验结果调用 `on_change`
+    pub fn new<F>(path: impl AsRef<Path>, mut on_change: F) -> Result<Self>
+    where
+        F: FnMut(Result<RuleSet, Vec<ConfigDiagnostic>>) + Send + 'static,
+    {
+        let path = path.as_ref().to_path_buf();
+        let file_name = path
+            .file_name()
+            .with_context(|| format!("{} is not a file path", path.display()))?
+            .to_owned();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+                match res {
+                    // Reading the file ourselves produces access events, ignore them
+                    // 我们自己读取文件也会产生访问事件, 需要忽略
+                    Ok(event) if matches!(event.kind, EventKind::Access(_)) => {}
+                    Ok(event) => {
+                        if event
+                            .paths
+                            .iter()
+                            .any(|p| p.file_name() == Some(&file_name))
+                        {
+                            let _ = tx.send(());
+                        }
+                    }
+                    Err(e) => warn!("[RulesWatcher] Watch error: {}", e),
+                }
+            })?;
+        watcher
+            .watch(&dir, RecursiveMode::NonRecursive)
+            .with_context(|| format!("failed to watch {}", dir.display()))?;
+
+        let reload_path = path.clone();
+        let handle = MonitorHandle::spawn("rules-watcher", move |stop| {
+            info!(
+                "[RulesWatcher] Watching {} for changes.",
+                reload_path.display()
+            );
+            let (stop_tx, stop_rx) = crossbeam_channel::bounded(1);
+            stop.on_stop(move || {
+                let _ = stop_tx.try_send(());
+            });
+
+            loop {
+                // The sender lives in the watcher callback, so a closed channel means the
+                // watcher is gone
+                // 发送端在监听器的回调里, 通道关闭说明监听器已不存在
+                crossbeam_channel::select! {
+                    recv(stop_rx) -> _ => return,
+                    recv(rx) -> event => if event.is_err() {
+                        return;
+                    },
+                }
+                loop {
+                    match rx.recv_timeout(DEBOUNCE) {
+                        Ok(()) => {}
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                if stop.is_stopped() {
+                    return;
+                }
+
+                let result = load_validated(&reload_path);
+                match &result {
+                    Ok(rule_set) => info!(
+                        "[RulesWatcher] Reloaded {} ({} rules).",
+                        reload_path.display(),
+                        rule_set.rules.len()
+                    ),
+                    Err(diagnostics) => {
+                        for diagnostic in diagnostics {
+                            warn!("[RulesWatcher] {}: {}", reload_path.display(), diagnostic);
+                        }
+                    }
+                }
+                on_change(result);
+            }
+        })?;
+
+        Ok(Self {
+            path,
+            _watcher: watcher,
+            _handle: handle,
+        })
+    }
+
+    /// 被监听的规则文件
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::validate::DiagnosticKind;
+
+    #[test]
+    fn file_changes_reach_the_callback() {
+        let dir = std::env::temp_dir().join(format!("usb-resolver-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rules.json");
+        fs::write(&path, "[]").unwrap();
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let watcher = RulesWatcher::new(&path, move |result| tx.send(result).unwrap()).unwrap();
+        let next = || rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        fs::write(
+            &path,
+            r#"[{"role": "arm", "vid": "0x1a86", "pid": "0x55d3"}]"#,
+        )
+        .unwrap();
+        let rule_set = next().unwrap();
+        assert_eq!(rule_set.rules[0].role, "arm");
+
+        // Broken files are reported with their diagnostics, not loaded
+        // 有问题的文件连同诊断一起上报, 不会被加载
+        fs::write(&path, r#"[{"role": "arm", "vid": 6790,}]"#).unwrap();
+        let diagnostics = next().unwrap_err();
+        assert!(matches!(
+            diagnostics[0].kind,
+            DiagnosticKind::Parse { line: 1, .. }
+        ));
+
+        fs::write(
+            &path,
+            r#"[{"role": "arm", "vid": 6790, "pid": 1}, {"role": "arm", "vid": 6790, "pid": 2}]"#,
+        )
+        .unwrap();
+        let diagnostics = next().unwrap_err();
+        assert!(diagnostics.iter().any(ConfigDiagnostic::is_error));
+
+        // Dropping the watcher stops the reload thread, which drops the callback
+        // drop 监听器会停止重载线程, 回调随之被释放
+        drop(watcher);
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+// Parsing of the fixture tree in tests/fixtures/sysfs: a root hub with an external hub, a
+// CH340 and a UVC camera behind it, a CH343 with a udev database entry and an FT232R without
+// 解析 tests/fixtures/sysfs 中的目录树: 根集线器下接一个外部集线器 (其下有 CH340 与 UVC 摄像头),
+// 以及一个有 udev 数据库记录的 CH343 和一个没有记录的 FT232R
+#![cfg(target_os = "linux")]
+
+use usb_resolver::{NodeKind, RawDeviceInfo, SysfsEnumerator};
+
+const USB1: &str = "/sys/devices/pci0000:00/0000:00:14.0/usb1";
+
+fn enumerator() -> SysfsEnumerator {
+    SysfsEnumerator::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/sysfs"))
+}
+
+fn device(port: &str) -> RawDeviceInfo {
+    enumerator()
+        .device(&format!("{}/{}", USB1, port))
+        .unwrap_or_else(|| panic!("no usb_device at {}", port))
+}
+
+#[test]
+fn scan_finds_every_usb_device() {
+    let devices = enumerator().scan().unwrap();
+    let ids: Vec<String> = devices
+        .iter()
+        .map(|d| format!("{:04x}:{:04x}", d.vid, d.pid))
+        .collect();
+    assert_eq!(
+        ids,
+        [
+            "1d6b:0002",
+            "05e3:0610",
+            "1a86:7523",
+            "046d:0825",
+            "1a86:55d3",
+            "0403:6001"
+        ]
+    );
+
+    // Interfaces and child nodes are not devices of their own
+    // 接口与子节点不会被当作独立的设备
+    assert!(
+        enumerator()
+            .device(&format!("{}/1-2/1-2:1.0", USB1))
+            .is_none()
+    );
+}
+
+#[test]
+fn hubs_report_their_class() {
+    let hub = device("1-1");
+    assert_eq!(hub.device_class, Some(0x09));
+    assert_eq!(hub.interface_classes, [0x09]);
+    assert_eq!(hub.drivers, ["hub"]);
+    assert_eq!(hub.port_path, "pci-0000:00:14.0-usb-0:1");
+    assert_eq!(hub.product.as_deref(), Some("USB2.0 Hub"));
+
+    // The devices plugged into the hub are not its children
+    // 插在集线器上的设备不是它的子节点
+    assert!(hub.children.is_empty());
+    assert_eq!(hub.system_path_alt, None);
+}
+
+#[test]
+fn ch340_without_udev_data_falls_back_to_sysfs() {
+    let ch340 = device("1-1/1-1.1");
+    assert_eq!((ch340.vid, ch340.pid), (0x1a86, 0x7523));
+    assert_eq!(ch340.serial, None);
+    assert_eq!(ch340.port_path, "N/A");
+    assert_eq!(ch340.bcd_device, Some(0x0264));
+    assert_eq!(ch340.drivers, ["ch341"]);
+    assert_eq!(ch340.system_path_alt.as_deref(), Some("/dev/ttyUSB0"));
+
+    let tty = &ch340.children[0];
+    assert_eq!(
+        (tty.subsystem.as_str(), tty.kind, tty.interface_number),
+        ("tty", NodeKind::Serial, Some(0))
+    );
+    assert!(tty.links.is_empty());
+}
+
+#[test]
+fn serial_comes_from_udev_then_sysfs() {
+    let ch343 = device("1-2");
+    assert_eq!(ch343.serial.as_deref(), Some("5AB0183575"));
+    assert_eq!(ch343.port_path, "pci-0000:00:14.0-usb-0:2");
+    assert_eq!(ch343.manufacturer.as_deref(), Some("QinHeng Electronics"));
+    assert_eq!(ch343.interface_classes, [0x02, 0x0a]);
+    assert_eq!(ch343.drivers, ["cdc_acm"]);
+    assert_eq!(ch343.system_path_alt.as_deref(), Some("/dev/ttyACM0"));
+    assert_eq!(
+        ch343.children[0].links,
+        [
+            "/dev/serial/by-id/usb-1a86_USB_Single_Serial_5AB0183575-if00",
+            "/dev/serial/by-path/pci-0000:00:14.0-usb-0:2:1.0",
+        ]
+    );
+
+    let ftdi = device("1-3");
+    assert_eq!(ftdi.serial.as_deref(), Some("A50285BI"));
+    assert_eq!(ftdi.port_path, "N/A");
+    assert_eq!(ftdi.system_path_alt.as_deref(), Some("/dev/ttyUSB1"));
+}
+
+#[test]
+fn uvc_camera_nodes() {
+    let camera = device("1-1/1-1.2");
+    assert_eq!(camera.serial.as_deref(), Some("8A3B5C1F"));
+    assert_eq!(camera.device_class, Some(0xef));
+    assert_eq!(camera.interface_classes, [0x0e, 0x01]);
+    assert_eq!(camera.drivers, ["uvcvideo", "snd-usb-audio"]);
+    assert_eq!(camera.system_path_alt.as_deref(), Some("/dev/video0"));
+
+    let nodes: Vec<(&str, NodeKind)> = camera
+        .children
+        .iter()
+        .map(|c| (c.devnode.as_str(), c.kind))
+        .collect();
+    assert_eq!(
+        nodes,
+        [
+            ("/dev/video0", NodeKind::Capture),
+            ("/dev/video1", NodeKind::Metadata)
+        ]
+    );
+    assert_eq!(
+        camera.children[0].links[0],
+        "/dev/v4l/by-id/usb-046d_0825_8A3B5C1F-video-index0"
+    );
+}